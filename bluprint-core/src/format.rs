@@ -0,0 +1,502 @@
+//! Reading and writing `.blu` map files.
+//!
+//! Maps are stored either as JSON (`.blu`), or in the more compact [`binary`] format
+//! (`.blub`). JSON maps start with the version of the format they were written with (see
+//! [`CURRENT_VERSION`]). Older files are upgraded when they are loaded. The tile chunks are
+//! written as a list rather than an object, because JSON object keys can only be strings:
+//!
+//! ```json
+//! {
+//!   "version": 8,
+//!   "metadata": {
+//!     "title": "The Sunken Crypt",
+//!     "author": "Jo",
+//!     "description": "A two-level dungeon for 3rd level characters.",
+//!     "grid_unit": { "amount": 5.0, "unit": "ft" },
+//!     "created": 1767225600,
+//!     "modified": 1767312000,
+//!     "tags": ["dungeon", "undead"]
+//!   },
+//!   "tile_set": [
+//!     { "id": 0, "name": "Floor", "solid": true, "opaque": true, "movement_cost": 1, "color": "#8b7355" }
+//!   ],
+//!   "chunk_size": { "x": 16, "y": 64, "z": 16 },
+//!   "layers": [
+//!     {
+//!       "name": "Terrain",
+//!       "visible": true,
+//!       "locked": false,
+//!       "chunks": [
+//!         {
+//!           "position": { "x": 0, "y": 0, "z": 0 },
+//!           "tiles": [[[null, { "tile_id": 0 }, { "tile_id": 1, "facing": ["Up", "East"] }, ...], ...], ...]
+//!         }
+//!       ],
+//!       "tile_data": [
+//!         { "position": { "x": 3, "y": 0, "z": 5 }, "tint": "#ff0000", "tags": ["trap"], "note": "Pressure plate" }
+//!       ]
+//!     }
+//!   ],
+//!   "active_layer": 0,
+//!   "object_types": [
+//!     { "id": 0, "name": "Torch", "kind": "Light", "color": "#ffa500" }
+//!   ],
+//!   "objects": [
+//!     { "id": 0, "type_id": 0, "position": { "x": 2.5, "y": 1.0, "z": 0.5 }, "rotation": 90.0, "scale": 1.0 }
+//!   ],
+//!   "regions": [
+//!     {
+//!       "name": "1. Entrance Hall",
+//!       "boxes": [{ "min": { "x": 0, "y": 0, "z": 0 }, "max": { "x": 9, "y": 3, "z": 5 } }],
+//!       "description": "Dusty banners hang from the walls.",
+//!       "fields": { "encounter": "2 goblins" }
+//!     }
+//!   ]
+//! }
+//! ```
+//!
+//! `metadata` and all of its fields are optional. `created` and `modified` are seconds
+//! since the Unix epoch.
+//!
+//! The tile set is embedded in the map, so every `tile_id` in `chunks` must match the `id`
+//! of one of its tile types. A tile type's properties other than `id` and `name` can be
+//! left out, and default to a solid, opaque, grey tile with a movement cost of 1.
+//!
+//! Layers are listed from the bottom up. Each has its own `chunks`, whose `tiles` are
+//! indexed as `tiles[z][y][x]` and must have exactly the dimensions given by `chunk_size`.
+//! Empty cells are `null`, and tiles without a `facing` face up and north. Chunks are
+//! written in order of their position so that saving the same map twice gives the same
+//! file.
+//!
+//! A layer's `tile_data` is optional, and only lists the cells that have data. Each entry's
+//! `tint`, `tags` and `note` are optional too.
+//!
+//! `object_types` and `objects` are optional. Objects aren't on a layer, and their
+//! positions don't have to be whole numbers. Every object's `type_id` must match the `id`
+//! of one of the map's object types.
+//!
+//! `regions` is optional, as are each region's `description` and `fields`. A region's
+//! `boxes` include both corners.
+
+use crate::objects::ObjectId;
+use crate::tiles::{ChunkPos, ChunkSize, LocalPos, Tile, TileChunk, TileData, TileMap, WorldPos};
+use serde::de::{self, Deserializer};
+use serde::ser::Serializer;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+pub mod binary;
+mod migrations;
+
+pub use migrations::CURRENT_VERSION;
+
+/// The two encodings a map can be saved in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MapFormat {
+    Json,
+    Binary,
+}
+
+impl MapFormat {
+    /// Picks a format from a file's extension: `.blub` is binary, anything else is JSON.
+    pub fn from_path(path: impl AsRef<Path>) -> Self {
+        match path.as_ref().extension().and_then(|ext| ext.to_str()) {
+            Some("blub") => MapFormat::Binary,
+            _ => MapFormat::Json,
+        }
+    }
+}
+
+/// An error encountered while reading or writing a map file.
+#[derive(Debug)]
+pub enum MapError {
+    Io(std::io::Error),
+    Parse(serde_json::Error),
+    /// The map file is truncated or malformed.
+    Corrupt(String),
+    /// The file was written by a newer version of Bluprint.
+    UnsupportedVersion(u32),
+    /// The file couldn't be upgraded to the current version.
+    Migration(String),
+    /// A tile refers to a tile type that isn't in the map's tile set.
+    UnknownTile { tile_id: usize, pos: WorldPos },
+    /// An object refers to an object type that isn't in the map's object types.
+    UnknownObjectType { type_id: usize, object: ObjectId },
+}
+
+impl fmt::Display for MapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MapError::Io(err) => write!(f, "{}", err),
+            MapError::Parse(err) => write!(f, "invalid map file: {}", err),
+            MapError::Corrupt(err) => write!(f, "corrupt map file: {}", err),
+            MapError::UnsupportedVersion(version) => write!(
+                f,
+                "map format version {} is newer than this version of Bluprint supports ({})",
+                version, CURRENT_VERSION
+            ),
+            MapError::Migration(err) => write!(f, "couldn't upgrade map: {}", err),
+            MapError::UnknownTile { tile_id, pos } => write!(
+                f,
+                "tile at ({}, {}, {}) has type {}, which isn't in the tile set",
+                pos.x, pos.y, pos.z, tile_id
+            ),
+            MapError::UnknownObjectType { type_id, object } => write!(
+                f,
+                "object {} has type {}, which isn't one of the map's object types",
+                object, type_id
+            ),
+        }
+    }
+}
+
+impl std::error::Error for MapError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            MapError::Io(err) => Some(err),
+            MapError::Parse(err) => Some(err),
+            MapError::Corrupt(_)
+            | MapError::UnsupportedVersion(_)
+            | MapError::Migration(_)
+            | MapError::UnknownTile { .. }
+            | MapError::UnknownObjectType { .. } => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for MapError {
+    fn from(err: std::io::Error) -> Self {
+        MapError::Io(err)
+    }
+}
+
+impl From<serde_json::Error> for MapError {
+    fn from(err: serde_json::Error) -> Self {
+        MapError::Parse(err)
+    }
+}
+
+/// Reads a map file in either format from disk, upgrading it to the current format if
+/// needed.
+pub fn load_map(path: impl AsRef<Path>) -> Result<TileMap, MapError> {
+    load_map_with_version(path).map(|(map, _)| map)
+}
+
+/// Like [`load_map`], but also returns the format version the file was written with.
+pub fn load_map_with_version(path: impl AsRef<Path>) -> Result<(TileMap, u32), MapError> {
+    let mut reader = BufReader::new(File::open(path)?);
+
+    let (map, version) = if reader.fill_buf()?.starts_with(binary::MAGIC) {
+        binary::read_map(reader)?
+    } else {
+        let mut doc: serde_json::Value = serde_json::from_reader(reader)?;
+        let version = migrations::migrate(&mut doc)?;
+        (TileMap::deserialize(doc)?, version)
+    };
+    validate(&map)?;
+
+    Ok((map, version))
+}
+
+/// Checks the things about a loaded map that deserializing it can't.
+fn validate(map: &TileMap) -> Result<(), MapError> {
+    let size = map.chunk_size();
+    if !size.is_valid() {
+        return Err(MapError::Corrupt(format!("invalid chunk size {:?}", size)));
+    }
+
+    // Every cell of every chunk needs a position that fits in an `i32`.
+    let in_range = |coord: i32, len: usize| {
+        let min = coord as i64 * len as i64;
+        i32::try_from(min).is_ok() && i32::try_from(min + len as i64 - 1).is_ok()
+    };
+
+    for (cpos, chunk) in map.layers().iter().flat_map(|layer| layer.chunks()) {
+        if chunk.size() != size {
+            return Err(MapError::Corrupt(format!(
+                "chunk at {:?} has size {:?}, but the map's chunk size is {:?}",
+                cpos,
+                chunk.size(),
+                size
+            )));
+        }
+        if !(in_range(cpos.x, size.x) && in_range(cpos.y, size.y) && in_range(cpos.z, size.z)) {
+            return Err(MapError::Corrupt(format!("chunk at {:?} is too far from the origin", cpos)));
+        }
+
+        for (index, tile) in chunk.tiles().enumerate() {
+            if let Some(tile) = tile {
+                if map.tile_set().get_tile(tile.tile_id).is_none() {
+                    return Err(MapError::UnknownTile {
+                        tile_id: tile.tile_id,
+                        pos: cpos.world_pos(size, size.pos_at(index)),
+                    });
+                }
+            }
+        }
+    }
+
+    for (id, object) in map.objects().iter() {
+        if id == ObjectId::MAX {
+            return Err(MapError::Corrupt(format!("object id {} is too large", id)));
+        }
+        if map.object_types().get_type(object.type_id).is_none() {
+            return Err(MapError::UnknownObjectType {
+                type_id: object.type_id,
+                object: id,
+            });
+        }
+    }
+
+    Ok(())
+}
+
+#[derive(Serialize)]
+struct VersionedMap<'a> {
+    version: u32,
+    #[serde(flatten)]
+    map: &'a TileMap,
+}
+
+/// Writes a map to disk in the current version of the format that matches the path's
+/// extension, replacing anything already at `path`.
+pub fn save_map(map: &TileMap, path: impl AsRef<Path>) -> Result<(), MapError> {
+    save_map_as(map, &path, MapFormat::from_path(&path))
+}
+
+/// Writes a map to disk in the given format, replacing anything already at `path`.
+///
+/// The map is written to a temporary file next to `path` first, and only moved over it
+/// once it's complete, so a failed save leaves the existing file as it was.
+pub fn save_map_as(map: &TileMap, path: impl AsRef<Path>, format: MapFormat) -> Result<(), MapError> {
+    let path = path.as_ref();
+    let temp = temp_path(path);
+    let result = write_map(map, &temp, format).and_then(|()| Ok(fs::rename(&temp, path)?));
+    if result.is_err() {
+        fs::remove_file(&temp).ok();
+    }
+    result
+}
+
+/// A hidden file in the same directory as `path`, so renaming it over `path` doesn't have
+/// to move it between file systems.
+fn temp_path(path: &Path) -> PathBuf {
+    let mut name = OsString::from(".");
+    name.push(path.file_name().unwrap_or_default());
+    name.push(".tmp");
+    path.with_file_name(name)
+}
+
+fn write_map(map: &TileMap, path: &Path, format: MapFormat) -> Result<(), MapError> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    match format {
+        MapFormat::Json => {
+            let doc = VersionedMap {
+                version: CURRENT_VERSION,
+                map,
+            };
+            serde_json::to_writer(&mut writer, &doc)?;
+        }
+        MapFormat::Binary => binary::write_map(map, &mut writer)?,
+    }
+    writer.flush()?;
+
+    Ok(())
+}
+
+impl Serialize for TileChunk {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        struct Row<'a>(&'a TileChunk, usize, usize);
+        struct Slice<'a>(&'a TileChunk, usize);
+
+        impl Serialize for Row<'_> {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                let Row(chunk, y, z) = *self;
+                serializer.collect_seq((0..chunk.size().x).map(|x| chunk.get_tile(LocalPos::new(x, y, z))))
+            }
+        }
+
+        impl Serialize for Slice<'_> {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                let Slice(chunk, z) = *self;
+                serializer.collect_seq((0..chunk.size().y).map(|y| Row(chunk, y, z)))
+            }
+        }
+
+        serializer.collect_seq((0..self.size().z).map(|z| Slice(self, z)))
+    }
+}
+
+impl<'de> Deserialize<'de> for TileChunk {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let tiles = Vec::<Vec<Vec<Option<Tile>>>>::deserialize(deserializer)?;
+
+        let z = tiles.len();
+        let y = tiles.first().map_or(0, Vec::len);
+        let x = tiles.first().and_then(|slice| slice.first()).map_or(0, Vec::len);
+        if x == 0 || y == 0 || z == 0 {
+            return Err(de::Error::custom("chunks can't be empty"));
+        }
+        if tiles.iter().any(|slice| slice.len() != y || slice.iter().any(|row| row.len() != x)) {
+            return Err(de::Error::custom("all rows and slices of a chunk must be the same length"));
+        }
+
+        let size = ChunkSize { x, y, z };
+        if !size.is_valid() {
+            return Err(de::Error::custom(format!(
+                "chunks can't have more than {} tiles",
+                ChunkSize::MAX_VOLUME
+            )));
+        }
+
+        let mut chunk = TileChunk::new(size);
+        for (z, slice) in tiles.into_iter().enumerate() {
+            for (y, row) in slice.into_iter().enumerate() {
+                for (x, tile) in row.into_iter().enumerate() {
+                    chunk.set_tile(LocalPos::new(x, y, z), tile);
+                }
+            }
+        }
+
+        Ok(chunk)
+    }
+}
+
+#[derive(Serialize)]
+struct ChunkEntryRef<'a> {
+    position: ChunkPos,
+    tiles: &'a TileChunk,
+}
+
+#[derive(Deserialize)]
+struct ChunkEntry {
+    position: ChunkPos,
+    tiles: TileChunk,
+}
+
+/// (De)serializes a map's chunks as a list of chunk entries, for use with
+/// `#[serde(with = "...")]`.
+pub(crate) mod chunk_list {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(
+        chunks: &HashMap<ChunkPos, Arc<TileChunk>>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        let mut entries: Vec<_> = chunks
+            .iter()
+            .map(|(position, tiles)| ChunkEntryRef {
+                position: *position,
+                tiles,
+            })
+            .collect();
+        entries.sort_by_key(|entry| entry.position);
+
+        serializer.collect_seq(entries)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<HashMap<ChunkPos, Arc<TileChunk>>, D::Error> {
+        let entries = Vec::<ChunkEntry>::deserialize(deserializer)?;
+        let mut chunks = HashMap::with_capacity(entries.len());
+        for entry in entries {
+            if chunks.insert(entry.position, Arc::new(entry.tiles)).is_some() {
+                return Err(de::Error::custom(format!(
+                    "duplicate chunk at {:?}",
+                    entry.position
+                )));
+            }
+        }
+
+        Ok(chunks)
+    }
+}
+
+#[derive(Serialize)]
+struct TileDataEntryRef<'a> {
+    position: WorldPos,
+    #[serde(flatten)]
+    data: &'a TileData,
+}
+
+#[derive(Deserialize)]
+struct TileDataEntry {
+    position: WorldPos,
+    #[serde(flatten)]
+    data: TileData,
+}
+
+/// (De)serializes a map's per-cell data as a list of entries sorted by position, for use
+/// with `#[serde(with = "...")]`.
+pub(crate) mod tile_data_list {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(
+        tile_data: &HashMap<WorldPos, TileData>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        let mut entries: Vec<_> = tile_data
+            .iter()
+            .filter(|(_, data)| !data.is_empty())
+            .map(|(position, data)| TileDataEntryRef {
+                position: *position,
+                data,
+            })
+            .collect();
+        entries.sort_by_key(|entry| entry.position);
+
+        serializer.collect_seq(entries)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<HashMap<WorldPos, TileData>, D::Error> {
+        let entries = Vec::<TileDataEntry>::deserialize(deserializer)?;
+        let mut tile_data = HashMap::with_capacity(entries.len());
+        for entry in entries {
+            if entry.data.is_empty() {
+                continue;
+            }
+            if tile_data.insert(entry.position, entry.data).is_some() {
+                return Err(de::Error::custom(format!(
+                    "duplicate tile data at {:?}",
+                    entry.position
+                )));
+            }
+        }
+
+        Ok(tile_data)
+    }
+}
+
+/// (De)serializes an optional time as a whole number of seconds since the Unix epoch, for
+/// use with `#[serde(with = "...")]`.
+pub(crate) mod unix_time {
+    use super::*;
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    pub fn serialize<S: Serializer>(time: &Option<SystemTime>, serializer: S) -> Result<S::Ok, S::Error> {
+        let seconds = time.map(|time| match time.duration_since(UNIX_EPOCH) {
+            Ok(after) => after.as_secs() as i64,
+            Err(before) => -(before.duration().as_secs() as i64),
+        });
+        seconds.serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<SystemTime>, D::Error> {
+        Ok(Option::<i64>::deserialize(deserializer)?.map(|seconds| {
+            if seconds >= 0 {
+                UNIX_EPOCH + Duration::from_secs(seconds as u64)
+            } else {
+                UNIX_EPOCH - Duration::from_secs(seconds.unsigned_abs())
+            }
+        }))
+    }
+}
@@ -0,0 +1,265 @@
+//! The binary map format, for maps too large to store comfortably as JSON.
+//!
+//! All integers are little-endian. A binary map file is laid out as:
+//!
+//! | Field         | Type                  |                                              |
+//! |---------------|-----------------------|----------------------------------------------|
+//! | magic         | `[u8; 4]`             | Always [`MAGIC`]                             |
+//! | header length | `u32`                 |                                              |
+//! | header        | JSON                  | The JSON map document, with no chunks        |
+//! | chunk count   | `u32`                 |                                              |
+//! | chunks        |                       | `chunk count` chunks, as below               |
+//!
+//! The header goes through the same migrations as a JSON map, so its `version` field also
+//! decides how the chunks that follow are decoded. Each chunk is:
+//!
+//! | Field          | Type              |                                                 |
+//! |----------------|-------------------|-------------------------------------------------|
+//! | layer          | `u16`             | Index into the header's `layers`                |
+//! | position       | `[i32; 3]`        | The chunk's `x`, `y` and `z`                    |
+//! | palette length | `u16`             |                                                 |
+//! | palette        |                   | Every distinct tile in the chunk, as below      |
+//! | run count      | `u32`             |                                                 |
+//! | runs           | `[(u16, u16)]`    | Run length, then index into the palette         |
+//!
+//! A palette entry is a `u8` that is `0` for an empty cell, or `1` followed by the tile's
+//! `tile_id` as a `u32` and its [`Facing::index`] as a `u8`. Before version 4, entries
+//! didn't have a facing. Runs cover the chunk's cells with `x` varying fastest, then `y`,
+//! then `z`, and their lengths must add up to exactly one chunk of the header's
+//! `chunk_size`.
+//!
+//! Before version 5, maps had a single layer and chunks didn't start with one.
+
+use super::{migrations, MapError, VersionedMap};
+use crate::tiles::{ChunkPos, ChunkSize, Facing, Tile, TileChunk, TileMap};
+use serde::Deserialize;
+use std::io::{Read, Write};
+
+/// The first four bytes of every binary map file.
+pub const MAGIC: &[u8; 4] = b"BLUB";
+
+pub(super) fn write_map(map: &TileMap, mut writer: impl Write) -> Result<(), MapError> {
+    let header = serde_json::to_vec(&VersionedMap {
+        version: migrations::CURRENT_VERSION,
+        map: &map.clone_without_chunks(),
+    })?;
+
+    writer.write_all(MAGIC)?;
+    write_len(&mut writer, header.len())?;
+    writer.write_all(&header)?;
+
+    let mut chunks: Vec<_> = map
+        .layers()
+        .iter()
+        .enumerate()
+        .flat_map(|(layer, chunks)| chunks.chunks().map(move |(pos, chunk)| (layer, pos, chunk)))
+        .collect();
+    chunks.sort_by_key(|(layer, pos, _)| (*layer, *pos));
+
+    write_len(&mut writer, chunks.len())?;
+    for (layer, pos, chunk) in chunks {
+        let layer = u16::try_from(layer)
+            .map_err(|_| MapError::Corrupt(format!("{} layers is too many", map.layers().len())))?;
+        writer.write_all(&layer.to_le_bytes())?;
+        write_chunk(&mut writer, pos, chunk)?;
+    }
+
+    Ok(())
+}
+
+pub(super) fn read_map(mut reader: impl Read) -> Result<(TileMap, u32), MapError> {
+    let mut magic = [0; 4];
+    reader.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        return Err(MapError::Corrupt("not a binary map file".to_owned()));
+    }
+
+    // Read through `take` rather than allocating the whole length up front, so a corrupt
+    // length can't ask for gigabytes.
+    let header_len = read_u32(&mut reader)? as u64;
+    let mut header = Vec::new();
+    reader.by_ref().take(header_len).read_to_end(&mut header)?;
+    if header.len() as u64 != header_len {
+        return Err(MapError::Corrupt(format!(
+            "header is {} bytes, expected {}",
+            header.len(),
+            header_len
+        )));
+    }
+    let mut doc: serde_json::Value = serde_json::from_slice(&header)?;
+    let version = migrations::migrate(&mut doc)?;
+    let mut map = TileMap::deserialize(doc)?;
+
+    let size = map.chunk_size();
+    if !size.is_valid() {
+        return Err(MapError::Corrupt(format!("invalid chunk size {:?}", size)));
+    }
+
+    let chunk_count = read_u32(&mut reader)?;
+    for _ in 0..chunk_count {
+        let index = if version < 5 { 0 } else { read_u16(&mut reader)? as usize };
+        let (pos, chunk) = read_chunk(&mut reader, size, version)?;
+
+        let layer = map
+            .layer_mut(index)
+            .ok_or_else(|| MapError::Corrupt(format!("chunk at {:?} is on missing layer {}", pos, index)))?;
+        if layer.get_chunk(pos).is_some() {
+            return Err(MapError::Corrupt(format!("duplicate chunk at {:?} on layer {}", pos, index)));
+        }
+        layer.insert_chunk(pos, chunk);
+    }
+
+    Ok((map, version))
+}
+
+fn write_chunk(writer: &mut impl Write, pos: ChunkPos, chunk: &TileChunk) -> Result<(), MapError> {
+    // Only the palette entries that are used are written, in the order they first appear,
+    // so that saving the same map twice gives the same file.
+    let mut palette: Vec<Option<Tile>> = Vec::new();
+    let runs = match chunk.palette_cells() {
+        Some((entries, cells)) => {
+            let mut remap: Vec<Option<u16>> = vec![None; entries.len()];
+            runs(cells.iter().map(|&cell| {
+                *remap[cell as usize].get_or_insert_with(|| {
+                    palette.push(entries[cell as usize]);
+                    (palette.len() - 1) as u16
+                })
+            }))
+        }
+        None => {
+            palette.push(None);
+            runs(std::iter::repeat_n(0, chunk.size().volume()))
+        }
+    };
+    // A chunk has at most `ChunkSize::MAX_VOLUME` cells, so this can only fail for a
+    // chunk size that wasn't checked.
+    let palette_len = u16::try_from(palette.len())
+        .map_err(|_| MapError::Corrupt(format!("chunk at {:?} has too many distinct tiles", pos)))?;
+
+    for coord in [pos.x, pos.y, pos.z] {
+        writer.write_all(&coord.to_le_bytes())?;
+    }
+
+    writer.write_all(&palette_len.to_le_bytes())?;
+    for tile in palette {
+        match tile {
+            None => writer.write_all(&[0])?,
+            Some(tile) => {
+                let tile_id = u32::try_from(tile.tile_id)
+                    .map_err(|_| MapError::Corrupt(format!("tile id {} is too large", tile.tile_id)))?;
+                writer.write_all(&[1])?;
+                writer.write_all(&tile_id.to_le_bytes())?;
+                writer.write_all(&[tile.facing.index()])?;
+            }
+        }
+    }
+
+    write_len(writer, runs.len())?;
+    for (len, idx) in runs {
+        writer.write_all(&len.to_le_bytes())?;
+        writer.write_all(&idx.to_le_bytes())?;
+    }
+
+    Ok(())
+}
+
+fn read_chunk(
+    reader: &mut impl Read,
+    size: ChunkSize,
+    version: u32,
+) -> Result<(ChunkPos, TileChunk), MapError> {
+    let pos = ChunkPos::new(read_i32(reader)?, read_i32(reader)?, read_i32(reader)?);
+
+    let palette_len = read_u16(reader)?;
+    let mut palette = Vec::with_capacity(palette_len as usize);
+    for _ in 0..palette_len {
+        let tile = match read_u8(reader)? {
+            0 => None,
+            1 => {
+                let tile = Tile::new(read_u32(reader)? as usize);
+                if version < 4 {
+                    Some(tile)
+                } else {
+                    let index = read_u8(reader)?;
+                    let facing = Facing::from_index(index)
+                        .ok_or_else(|| MapError::Corrupt(format!("invalid facing {}", index)))?;
+                    Some(tile.with_facing(facing))
+                }
+            }
+            tag => return Err(MapError::Corrupt(format!("invalid palette entry {}", tag))),
+        };
+        palette.push(tile);
+    }
+
+    let mut cells: Vec<u16> = Vec::with_capacity(size.volume());
+    let run_count = read_u32(reader)?;
+    for _ in 0..run_count {
+        let len = read_u16(reader)? as usize;
+        let idx = read_u16(reader)?;
+
+        if cells.len() + len > size.volume() {
+            return Err(MapError::Corrupt(format!(
+                "chunk at {:?} has more than {} tiles",
+                pos,
+                size.volume()
+            )));
+        }
+        cells.extend(std::iter::repeat_n(idx, len));
+    }
+
+    if cells.len() != size.volume() {
+        return Err(MapError::Corrupt(format!(
+            "chunk at {:?} has {} tiles, expected {}",
+            pos,
+            cells.len(),
+            size.volume()
+        )));
+    }
+
+    let chunk = TileChunk::from_palette_cells(size, &palette, &cells)
+        .ok_or_else(|| MapError::Corrupt(format!("chunk at {:?} has a palette index out of range", pos)))?;
+
+    Ok((pos, chunk))
+}
+
+/// Run-length encodes palette indices.
+fn runs(cells: impl Iterator<Item = u16>) -> Vec<(u16, u16)> {
+    let mut runs: Vec<(u16, u16)> = Vec::new();
+    for idx in cells {
+        match runs.last_mut() {
+            Some((len, run_idx)) if *run_idx == idx && *len < u16::MAX => *len += 1,
+            _ => runs.push((1, idx)),
+        }
+    }
+    runs
+}
+
+fn write_len(writer: &mut impl Write, len: usize) -> Result<(), MapError> {
+    let len = u32::try_from(len).map_err(|_| MapError::Corrupt(format!("{} is too long", len)))?;
+    writer.write_all(&len.to_le_bytes())?;
+    Ok(())
+}
+
+fn read_u8(reader: &mut impl Read) -> std::io::Result<u8> {
+    let mut buf = [0; 1];
+    reader.read_exact(&mut buf)?;
+    Ok(buf[0])
+}
+
+fn read_u16(reader: &mut impl Read) -> std::io::Result<u16> {
+    let mut buf = [0; 2];
+    reader.read_exact(&mut buf)?;
+    Ok(u16::from_le_bytes(buf))
+}
+
+fn read_u32(reader: &mut impl Read) -> std::io::Result<u32> {
+    let mut buf = [0; 4];
+    reader.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+fn read_i32(reader: &mut impl Read) -> std::io::Result<i32> {
+    let mut buf = [0; 4];
+    reader.read_exact(&mut buf)?;
+    Ok(i32::from_le_bytes(buf))
+}
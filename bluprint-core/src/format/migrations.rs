@@ -0,0 +1,137 @@
+//! Upgrades map files written by older versions of Bluprint.
+//!
+//! Every change to the file format bumps the version and adds a step to [`MIGRATIONS`].
+//! Old files are upgraded one version at a time, on the raw JSON, before they are
+//! deserialized.
+
+use super::MapError;
+use serde_json::{Map, Value};
+
+type Migration = fn(&mut Map<String, Value>) -> Result<(), String>;
+
+/// `MIGRATIONS[n]` upgrades a version `n + 1` map to version `n + 2`.
+const MIGRATIONS: &[Migration] = &[
+    v1_to_v2, v2_to_v3, v3_to_v4, v4_to_v5, v5_to_v6, v6_to_v7, v7_to_v8,
+];
+
+/// The format version written by this version of Bluprint.
+pub const CURRENT_VERSION: u32 = MIGRATIONS.len() as u32 + 1;
+
+/// Upgrades a map document in place, returning the version it was saved with.
+///
+/// Files without a `version` field predate versioning, and are treated as version 1.
+pub(super) fn migrate(doc: &mut Value) -> Result<u32, MapError> {
+    let map = doc
+        .as_object_mut()
+        .ok_or_else(|| MapError::Migration("map file isn't a JSON object".to_owned()))?;
+
+    let version = match map.remove("version") {
+        None => 1,
+        Some(version) => version
+            .as_u64()
+            .filter(|version| *version >= 1)
+            .and_then(|version| u32::try_from(version).ok())
+            .ok_or_else(|| MapError::Migration(format!("invalid version {}", version)))?,
+    };
+
+    if version > CURRENT_VERSION {
+        return Err(MapError::UnsupportedVersion(version));
+    }
+
+    for (from, migration) in MIGRATIONS.iter().enumerate().skip(version as usize - 1) {
+        migration(map).map_err(|err| {
+            MapError::Migration(format!(
+                "upgrading from version {} to {}: {}",
+                from + 1,
+                from + 2,
+                err
+            ))
+        })?;
+    }
+
+    Ok(version)
+}
+
+/// Version 1 didn't store a tile set, and always used the example one.
+fn v1_to_v2(map: &mut Map<String, Value>) -> Result<(), String> {
+    if !map.contains_key("tile_set") {
+        let tile_set = serde_json::to_value(crate::example::tileset()).map_err(|err| err.to_string())?;
+        map.insert("tile_set".to_owned(), tile_set);
+    }
+
+    Ok(())
+}
+
+/// Version 2 had a fixed chunk size of 16 x 64 x 16, and stored each chunk's tiles as one
+/// flat list.
+fn v2_to_v3(map: &mut Map<String, Value>) -> Result<(), String> {
+    const SIZE: (usize, usize, usize) = (16, 64, 16);
+
+    map.insert(
+        "chunk_size".to_owned(),
+        serde_json::json!({ "x": SIZE.0, "y": SIZE.1, "z": SIZE.2 }),
+    );
+
+    let chunks = match map.get_mut("chunks") {
+        Some(Value::Array(chunks)) => chunks,
+        _ => return Ok(()),
+    };
+
+    for chunk in chunks {
+        let tiles = match chunk.get_mut("tiles") {
+            Some(Value::Array(tiles)) => std::mem::take(tiles),
+            _ => return Err("chunk has no tiles".to_owned()),
+        };
+        if tiles.len() != SIZE.0 * SIZE.1 * SIZE.2 {
+            return Err(format!("chunk has {} tiles", tiles.len()));
+        }
+
+        let mut tiles = tiles.into_iter();
+        let nested: Vec<Value> = (0..SIZE.2)
+            .map(|_| {
+                let slice = (0..SIZE.1)
+                    .map(|_| Value::Array(tiles.by_ref().take(SIZE.0).collect()))
+                    .collect();
+                Value::Array(slice)
+            })
+            .collect();
+        chunk["tiles"] = Value::Array(nested);
+    }
+
+    Ok(())
+}
+
+/// Version 4 gave tiles a facing. JSON tiles without one face up and north, so only binary
+/// chunks, which are decoded knowing the version, are laid out differently.
+fn v3_to_v4(_map: &mut Map<String, Value>) -> Result<(), String> {
+    Ok(())
+}
+
+/// Version 4 maps had a single set of chunks and tile data, which become the only layer.
+fn v4_to_v5(map: &mut Map<String, Value>) -> Result<(), String> {
+    let mut layer = Map::new();
+    layer.insert("name".to_owned(), Value::from(crate::tiles::TileMap::DEFAULT_LAYER_NAME));
+    for key in ["chunks", "tile_data"] {
+        if let Some(value) = map.remove(key) {
+            layer.insert(key.to_owned(), value);
+        }
+    }
+    map.insert("layers".to_owned(), Value::Array(vec![Value::Object(layer)]));
+
+    Ok(())
+}
+
+/// Version 6 added `object_types` and `objects`, which older maps don't have.
+fn v5_to_v6(_map: &mut Map<String, Value>) -> Result<(), String> {
+    Ok(())
+}
+
+/// Version 7 added `regions`, which older maps don't have.
+fn v6_to_v7(_map: &mut Map<String, Value>) -> Result<(), String> {
+    Ok(())
+}
+
+/// Version 8 added `metadata`, which older maps don't have.
+fn v7_to_v8(_map: &mut Map<String, Value>) -> Result<(), String> {
+    Ok(())
+}
@@ -0,0 +1,668 @@
+//! Undo and redo for edits to a map's tiles and layers.
+//!
+//! Edits go through a [`History`], which records what they changed so they can be undone
+//! and redone later. Changes to tiles are recorded along with the index of the layer they
+//! were made on, so layers should only be added, removed or reordered through the history
+//! too.
+
+use crate::prefab::{PasteOptions, Prefab};
+use crate::tiles::{
+    Axis, Bounds, ChunkPos, FillTooLarge, FloodFill, Layer, Rotation, Shape, Tile, TileChunk, TileData, TileMap,
+    WorldPos,
+};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::mem;
+use std::sync::Arc;
+
+/// An edit to a map, which a [`History`] can undo. The tile commands edit the active layer.
+///
+/// Flood fills aren't commands, because they can fail. They're run with
+/// [`History::flood_fill`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    SetTile {
+        pos: WorldPos,
+        tile: Option<Tile>,
+    },
+    SetTileRange {
+        bounds: Bounds,
+        tile: Option<Tile>,
+    },
+    FillShape {
+        shape: Shape,
+        tile: Option<Tile>,
+    },
+    /// Replaces a cell's data. Empty data removes it.
+    SetTileData {
+        pos: WorldPos,
+        data: TileData,
+    },
+    PastePrefab {
+        prefab: Prefab,
+        options: PasteOptions,
+    },
+    /// Adds an empty layer on top of the others, and makes it active.
+    AddLayer {
+        name: String,
+    },
+    /// See [`TileMap::remove_layer`]. Does nothing if the layer can't be removed.
+    RemoveLayer {
+        index: usize,
+    },
+    /// See [`TileMap::move_layer`].
+    MoveLayer {
+        from: usize,
+        to: usize,
+    },
+    /// See [`TileMap::merge_layers`].
+    MergeLayers {
+        from: usize,
+        into: usize,
+    },
+    RotateY(Rotation),
+    Mirror(Axis),
+    Translate {
+        dx: i32,
+        dy: i32,
+        dz: i32,
+    },
+}
+
+impl Command {
+    /// Whether the command would change a [locked](Layer::locked) layer, which
+    /// [`History::apply`] won't do. Moving a locked layer up or down doesn't change it.
+    pub fn is_locked(&self, map: &TileMap) -> bool {
+        let locked = |index: usize| map.layer(index).is_some_and(|layer| layer.locked);
+        match self {
+            Command::SetTile { .. }
+            | Command::SetTileRange { .. }
+            | Command::FillShape { .. }
+            | Command::SetTileData { .. }
+            | Command::PastePrefab { .. } => locked(map.active_layer_index()),
+            Command::AddLayer { .. } | Command::MoveLayer { .. } => false,
+            Command::RemoveLayer { index } => locked(*index),
+            Command::MergeLayers { from, into } => locked(*from) || locked(*into),
+            Command::RotateY(_) | Command::Mirror(_) | Command::Translate { .. } => {
+                map.layers().iter().any(|layer| layer.locked)
+            }
+        }
+    }
+
+    /// Runs a command that edits the active layer's tiles and data, which is recorded by
+    /// comparing the layer before and after. Any other command isn't run, and the change it
+    /// would make is returned instead.
+    fn run(self, map: &mut TileMap) -> Option<Change> {
+        let active = map.active_layer_index();
+        match self {
+            Command::SetTile { pos, tile } => map.set_tile_at(pos, tile),
+            Command::SetTileRange { bounds, tile } => map.set_tile_range(bounds.min()..=bounds.max(), tile),
+            Command::FillShape { shape, tile } => map.fill_shape(&shape, tile),
+            Command::SetTileData { pos, data } => map.set_tile_data(pos, data),
+            Command::PastePrefab { prefab, options } => {
+                map.paste_prefab(&prefab, &options);
+            }
+            Command::AddLayer { name } => {
+                let index = map.layers().len();
+                return Some(Change::AddLayer { index, name, active });
+            }
+            Command::RemoveLayer { index } => {
+                if map.layers().len() > 1 {
+                    let layer = Box::new(map.layer(index)?.clone());
+                    return Some(Change::RemoveLayer { index, layer, active });
+                }
+            }
+            Command::MoveLayer { from, to } => return Some(Change::MoveLayer { from, to }),
+            Command::MergeLayers { from, into } => {
+                assert!(from < map.layers().len(), "no layer at index {}", from);
+                assert!(into < map.layers().len(), "no layer at index {}", into);
+                return Some(Change::MergeLayers {
+                    from,
+                    into,
+                    source: Box::new(map.layers()[from].clone()),
+                    target: Box::new(map.layers()[into].clone()),
+                    active,
+                });
+            }
+            Command::RotateY(rotation) => return Some(Change::RotateY(rotation)),
+            Command::Mirror(axis) => return Some(Change::Mirror(axis)),
+            Command::Translate { dx, dy, dz } => return Some(Change::Translate { dx, dy, dz }),
+        }
+        None
+    }
+}
+
+/// What edits, undos and redos have changed since [`History::take_changes`] was last
+/// called, so anything drawing the map knows what to update.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Changes {
+    /// The chunks with a changed tile or changed data in them, by layer index and position.
+    pub chunks: HashSet<(usize, ChunkPos)>,
+    /// Whether the map's layers were added, removed or reordered, or everything in it was
+    /// moved, so any chunk on any layer might have changed.
+    pub everything: bool,
+}
+
+/// A change to one cell of a layer, or to the whole map.
+#[derive(Debug, Clone, PartialEq)]
+enum Change {
+    Tile {
+        layer: usize,
+        pos: WorldPos,
+        before: Option<Tile>,
+        after: Option<Tile>,
+    },
+    /// Empty data is no data.
+    Data {
+        layer: usize,
+        pos: WorldPos,
+        before: TileData,
+        after: TileData,
+    },
+    /// `active` is the layer that was active before.
+    AddLayer {
+        index: usize,
+        name: String,
+        active: usize,
+    },
+    RemoveLayer {
+        index: usize,
+        layer: Box<Layer>,
+        active: usize,
+    },
+    MoveLayer {
+        from: usize,
+        to: usize,
+    },
+    /// `source` is the layer at `from` and `target` is the layer at `into`, before they
+    /// were merged.
+    MergeLayers {
+        from: usize,
+        into: usize,
+        source: Box<Layer>,
+        target: Box<Layer>,
+        active: usize,
+    },
+    RotateY(Rotation),
+    Mirror(Axis),
+    Translate {
+        dx: i32,
+        dy: i32,
+        dz: i32,
+    },
+}
+
+impl Change {
+    /// Whether undoing or redoing the change would change a locked layer.
+    fn is_locked(&self, map: &TileMap, undo: bool) -> bool {
+        let locked = |index: usize| map.layer(index).is_some_and(|layer| layer.locked);
+        match self {
+            Change::Tile { layer, .. } | Change::Data { layer, .. } => locked(*layer),
+            Change::AddLayer { index, .. } => undo && locked(*index),
+            Change::RemoveLayer { index, .. } => !undo && locked(*index),
+            Change::MoveLayer { .. } => false,
+            Change::MergeLayers { from, into, .. } if undo => locked(if into > from { into - 1 } else { *into }),
+            Change::MergeLayers { from, into, .. } => locked(*from) || locked(*into),
+            Change::RotateY(_) | Change::Mirror(_) | Change::Translate { .. } => {
+                map.layers().iter().any(|layer| layer.locked)
+            }
+        }
+    }
+
+    /// Roughly how many bytes the change takes up, counting its data's strings.
+    fn memory(&self) -> usize {
+        match self {
+            Change::Tile { .. } => mem::size_of::<Self>(),
+            Change::Data { before, after, .. } => mem::size_of::<Self>() + before.memory() + after.memory(),
+            Change::AddLayer { name, .. } => mem::size_of::<Self>() + name.len(),
+            Change::RemoveLayer { layer, .. } => mem::size_of::<Self>() + layer.memory(),
+            // Merging only modifies the target's chunks that the source has chunks at, so the
+            // rest are still shared with the map.
+            Change::MergeLayers { source, target, .. } => {
+                let modified: usize = source
+                    .chunks()
+                    .filter_map(|(cpos, _)| target.get_chunk(cpos))
+                    .map(TileChunk::memory)
+                    .sum();
+                let data: usize = target.tile_data().map(|(_, data)| data.memory()).sum();
+                mem::size_of::<Self>() + source.memory() + modified + data
+            }
+            Change::MoveLayer { .. } | Change::RotateY(_) | Change::Mirror(_) | Change::Translate { .. } => {
+                mem::size_of::<Self>()
+            }
+        }
+    }
+
+    /// Puts the map back how it was before the change, or how it was after it.
+    fn write(&self, map: &mut TileMap, undo: bool, changes: &mut Changes) {
+        let size = map.chunk_size();
+        match self {
+            Change::Tile {
+                layer,
+                pos,
+                before,
+                after,
+            } => {
+                if let Some(layer) = map.layer_mut(*layer) {
+                    layer.set_tile_at(*pos, if undo { *before } else { *after });
+                }
+                changes.chunks.insert((*layer, pos.chunk(size)));
+            }
+            Change::Data {
+                layer,
+                pos,
+                before,
+                after,
+            } => {
+                if let Some(layer) = map.layer_mut(*layer) {
+                    layer.set_tile_data(*pos, if undo { before } else { after }.clone());
+                }
+                changes.chunks.insert((*layer, pos.chunk(size)));
+            }
+            Change::AddLayer { index, name, active } => {
+                if undo {
+                    map.remove_layer(*index);
+                    map.set_active_layer(*active);
+                } else {
+                    map.add_layer(name.clone());
+                    map.set_active_layer(*index);
+                }
+                changes.everything = true;
+            }
+            Change::RemoveLayer { index, layer, active } => {
+                if undo {
+                    map.insert_layer(*index, (**layer).clone());
+                    map.set_active_layer(*active);
+                } else {
+                    map.remove_layer(*index);
+                }
+                changes.everything = true;
+            }
+            Change::MoveLayer { from, to } => {
+                if undo {
+                    map.move_layer(*to, *from);
+                } else {
+                    map.move_layer(*from, *to);
+                }
+                changes.everything = true;
+            }
+            Change::MergeLayers {
+                from,
+                into,
+                source,
+                target,
+                active,
+            } => {
+                if undo {
+                    // The merged layer's name and flags may have been changed since, and
+                    // they aren't part of the merge.
+                    let merged = map.layer_mut(if into > from { into - 1 } else { *into }).unwrap();
+                    let (name, visible, locked) = (mem::take(&mut merged.name), merged.visible, merged.locked);
+                    *merged = (**target).clone();
+                    merged.name = name;
+                    merged.visible = visible;
+                    merged.locked = locked;
+                    map.insert_layer(*from, (**source).clone());
+                    map.set_active_layer(*active);
+                } else {
+                    map.merge_layers(*from, *into);
+                }
+                changes.everything = true;
+            }
+            Change::RotateY(rotation) => {
+                map.rotate_y(if undo { rotation.inverse() } else { *rotation });
+                changes.everything = true;
+            }
+            Change::Mirror(axis) => {
+                map.mirror(*axis);
+                changes.everything = true;
+            }
+            Change::Translate { dx, dy, dz } => {
+                if undo {
+                    map.translate(-dx, -dy, -dz);
+                } else {
+                    map.translate(*dx, *dy, *dz);
+                }
+                changes.everything = true;
+            }
+        }
+    }
+}
+
+/// The changes undone or redone together, from one command or a group of them.
+#[derive(Debug, Clone, Default, PartialEq)]
+struct Step {
+    changes: Vec<Change>,
+    memory: usize,
+}
+
+impl Step {
+    fn push(&mut self, change: Change) {
+        self.memory += change.memory();
+        self.changes.push(change);
+    }
+
+    fn is_locked(&self, map: &TileMap, undo: bool) -> bool {
+        self.changes.iter().any(|change| change.is_locked(map, undo))
+    }
+}
+
+/// A layer's chunks and data before an edit. The chunks are shared with the layer, so an
+/// edit only copies the ones it modifies.
+struct LayerState {
+    index: usize,
+    chunks: HashMap<ChunkPos, Arc<TileChunk>>,
+    tile_data: HashMap<WorldPos, TileData>,
+}
+
+impl LayerState {
+    fn new(map: &TileMap, index: usize) -> Self {
+        let layer = &map.layers()[index];
+        Self {
+            index,
+            chunks: layer.shared_chunks(),
+            tile_data: layer.tile_data().map(|(pos, data)| (pos, data.clone())).collect(),
+        }
+    }
+
+    /// The changes made to the layer since, or `None` if they'd take up more than `limit`
+    /// bytes. Either way, every chunk with a change in it is added to `changes`.
+    ///
+    /// Only the chunks that were modified, and the cells with data, are compared.
+    fn diff(&self, map: &TileMap, limit: usize, changes: &mut Changes) -> Option<Step> {
+        let layer = &map.layers()[self.index];
+        let size = map.chunk_size();
+        let empty = TileChunk::new(size);
+
+        let mut step = Some(Step::default());
+        let push = |step: &mut Option<Step>, change: Change| {
+            if let Some(current) = step {
+                current.push(change);
+                if current.memory > limit {
+                    *step = None;
+                }
+            }
+        };
+
+        let positions: HashSet<ChunkPos> = self
+            .chunks
+            .keys()
+            .copied()
+            .chain(layer.chunks().map(|(cpos, _)| cpos))
+            .collect();
+        for cpos in positions {
+            let (old, new) = (self.chunks.get(&cpos), layer.shared_chunk(cpos));
+            let modified = match (old, new) {
+                (Some(old), Some(new)) => !Arc::ptr_eq(old, new),
+                (None, None) => false,
+                _ => true,
+            };
+            if !modified {
+                continue;
+            }
+
+            changes.chunks.insert((self.index, cpos));
+            if step.is_none() {
+                continue;
+            }
+            let (old, new) = (old.map_or(&empty, |chunk| chunk), new.map_or(&empty, |chunk| chunk));
+            for (index, (before, after)) in old.tiles().zip(new.tiles()).enumerate() {
+                if before != after {
+                    let change = Change::Tile {
+                        layer: self.index,
+                        pos: cpos.world_pos(size, size.pos_at(index)),
+                        before: *before,
+                        after: *after,
+                    };
+                    push(&mut step, change);
+                }
+            }
+        }
+
+        let positions: HashSet<WorldPos> = self
+            .tile_data
+            .keys()
+            .copied()
+            .chain(layer.tile_data().map(|(pos, _)| pos))
+            .collect();
+        for pos in positions {
+            let before = self.tile_data.get(&pos).cloned().unwrap_or_default();
+            let after = layer.get_tile_data(pos).cloned().unwrap_or_default();
+            if before != after {
+                changes.chunks.insert((self.index, pos.chunk(size)));
+                let change = Change::Data {
+                    layer: self.index,
+                    pos,
+                    before,
+                    after,
+                };
+                push(&mut step, change);
+            }
+        }
+
+        step
+    }
+}
+
+/// The edits made to a map that can be undone, and the undone edits that can be redone.
+///
+/// Edits can be grouped into one step, like the cells painted in one stroke of a brush.
+/// The oldest steps are forgotten when the history takes up more than its memory limit. A
+/// single step that's over the limit isn't kept at all, and since the steps before it
+/// wouldn't undo cleanly without it, they're forgotten too.
+#[derive(Debug, Clone, PartialEq)]
+pub struct History {
+    undo: VecDeque<Step>,
+    redo: Vec<Step>,
+    /// The step being built up by an open group.
+    group: Option<Step>,
+    memory_limit: usize,
+    /// What's changed since the last call to [`History::take_changes`].
+    changes: Changes,
+}
+
+impl History {
+    /// 64 MiB.
+    pub const DEFAULT_MEMORY_LIMIT: usize = 64 * 1024 * 1024;
+
+    pub fn new() -> Self {
+        Self::with_memory_limit(Self::DEFAULT_MEMORY_LIMIT)
+    }
+
+    /// A history that keeps about `memory_limit` bytes of edits.
+    pub fn with_memory_limit(memory_limit: usize) -> Self {
+        Self {
+            undo: VecDeque::new(),
+            redo: Vec::new(),
+            group: None,
+            memory_limit,
+            changes: Changes::default(),
+        }
+    }
+
+    /// Runs a command on the map, recording it so it can be undone. Does nothing if the
+    /// command [is locked](Command::is_locked).
+    pub fn apply(&mut self, map: &mut TileMap, command: Command) {
+        if command.is_locked(map) {
+            return;
+        }
+        if let Some(change) = self.record(map, |map| command.run(map)) {
+            change.write(map, false, &mut self.changes);
+            let mut step = Step::default();
+            step.push(change);
+            self.push(step);
+        }
+    }
+
+    /// Runs [`TileMap::flood_fill`], recording it so it can be undone. Nothing is changed or
+    /// recorded if the fill would be too large, or if the active layer is locked, which fills
+    /// no cells.
+    pub fn flood_fill(
+        &mut self,
+        map: &mut TileMap,
+        seed: impl Into<WorldPos>,
+        tile: Option<Tile>,
+        options: FloodFill,
+    ) -> Result<usize, FillTooLarge> {
+        if map.active_layer().locked {
+            return Ok(0);
+        }
+        let cells = map.active_layer().flood_fill_cells(seed, options)?;
+        self.record(map, |map| {
+            for &pos in &cells {
+                map.set_tile_at(pos, tile);
+            }
+        });
+        Ok(cells.len())
+    }
+
+    /// Runs any edit to the map's active layer, recording what it changes so it can be
+    /// undone. Changes to any other layer won't be undone.
+    pub fn record<T>(&mut self, map: &mut TileMap, edit: impl FnOnce(&mut TileMap) -> T) -> T {
+        let before = LayerState::new(map, map.active_layer_index());
+        let result = edit(map);
+
+        match before.diff(map, self.memory_limit, &mut self.changes) {
+            Some(step) => self.push(step),
+            None => self.forget_all(),
+        }
+        result
+    }
+
+    fn push(&mut self, step: Step) {
+        if step.changes.is_empty() {
+            return;
+        }
+        if step.memory > self.memory_limit {
+            self.forget_all();
+            return;
+        }
+
+        if let Some(group) = &mut self.group {
+            for change in step.changes {
+                group.push(change);
+            }
+            if group.memory > self.memory_limit {
+                self.forget_all();
+            }
+            return;
+        }
+
+        self.redo.clear();
+        self.undo.push_back(step);
+        while self.memory_used() > self.memory_limit {
+            if self.undo.pop_front().is_none() {
+                // Steps at the bottom of the redo stack are the furthest from being redone.
+                self.redo.remove(0);
+            }
+        }
+    }
+
+    /// Forgets every step after a change that's too large to record, leaving any open group
+    /// open so the rest of it is still recorded.
+    fn forget_all(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+        if let Some(group) = &mut self.group {
+            *group = Step::default();
+        }
+    }
+
+    /// Starts grouping edits, so they're undone and redone as one step. Does nothing if a
+    /// group is already open.
+    pub fn begin_group(&mut self) {
+        self.group.get_or_insert_with(Step::default);
+    }
+
+    /// Finishes the open group, if there is one.
+    pub fn end_group(&mut self) {
+        if let Some(group) = self.group.take() {
+            self.push(group);
+        }
+    }
+
+    /// Undoes the last step, closing any open group first. Returns `false` if there was
+    /// nothing to undo, or if undoing it would change a locked layer.
+    pub fn undo(&mut self, map: &mut TileMap) -> bool {
+        self.end_group();
+        if self.undo_is_locked(map) {
+            return false;
+        }
+        match self.undo.pop_back() {
+            Some(step) => {
+                for change in step.changes.iter().rev() {
+                    change.write(map, true, &mut self.changes);
+                }
+                self.redo.push(step);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Redoes the last undone step. Returns `false` if there was nothing to redo, or if
+    /// redoing it would change a locked layer.
+    pub fn redo(&mut self, map: &mut TileMap) -> bool {
+        self.end_group();
+        if self.redo_is_locked(map) {
+            return false;
+        }
+        match self.redo.pop() {
+            Some(step) => {
+                for change in &step.changes {
+                    change.write(map, false, &mut self.changes);
+                }
+                self.undo.push_back(step);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty() || self.group.as_ref().is_some_and(|group| !group.changes.is_empty())
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+    /// Whether undoing the last step would change a locked layer, so it can't be undone
+    /// until the layer is unlocked.
+    pub fn undo_is_locked(&self, map: &TileMap) -> bool {
+        let open = self.group.as_ref().filter(|group| !group.changes.is_empty());
+        open.or(self.undo.back()).is_some_and(|step| step.is_locked(map, true))
+    }
+
+    /// Whether redoing the last undone step would change a locked layer.
+    pub fn redo_is_locked(&self, map: &TileMap) -> bool {
+        self.redo.last().is_some_and(|step| step.is_locked(map, false))
+    }
+
+    /// Roughly how many bytes the recorded edits take up.
+    pub fn memory_used(&self) -> usize {
+        self.undo
+            .iter()
+            .chain(&self.redo)
+            .chain(&self.group)
+            .map(|step| step.memory)
+            .sum()
+    }
+
+    /// Takes what edits, undos and redos have changed since the last call.
+    pub fn take_changes(&mut self) -> Changes {
+        mem::take(&mut self.changes)
+    }
+
+    /// Forgets every edit, like after a change the history can't undo.
+    pub fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+        self.group = None;
+    }
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self::new()
+    }
+}
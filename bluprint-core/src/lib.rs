@@ -1,4 +1,11 @@
 #![feature(int_roundings)]
+pub mod format;
+pub mod history;
+pub mod metadata;
+pub mod objects;
+pub mod prefab;
+pub mod regions;
+pub mod rooms;
 pub mod tiles;
 pub mod utils;
 
@@ -11,8 +18,8 @@ pub mod example {
         tileset.add_tile(
             0,
             TileType {
-                id: 0,
-                name: "Floor".to_owned(),
+                color: Color::rgb(139, 115, 85),
+                ..TileType::new(0, "Floor")
             },
         );
 
@@ -22,10 +29,10 @@ pub mod example {
     pub fn tilemap() -> TileMap {
         let mut tilemap = TileMap::new(tileset());
 
-        tilemap.set_tile_range((0, 0, 0)..=(15, 0, 15), Some(Tile { tile_id: 0 }));
-        tilemap.set_tile_range((1, 1, 1)..=(15, 1, 15), Some(Tile { tile_id: 0 }));
-        tilemap.set_tile_range((2, 2, 2)..=(15, 2, 15), Some(Tile { tile_id: 0 }));
-        tilemap.set_tile_range((3, 3, 3)..=(15, 3, 15), Some(Tile { tile_id: 0 }));
+        tilemap.set_tile_range((0, 0, 0)..=(15, 0, 15), Some(Tile::new(0)));
+        tilemap.set_tile_range((1, 1, 1)..=(15, 1, 15), Some(Tile::new(0)));
+        tilemap.set_tile_range((2, 2, 2)..=(15, 2, 15), Some(Tile::new(0)));
+        tilemap.set_tile_range((3, 3, 3)..=(15, 3, 15), Some(Tile::new(0)));
 
         tilemap
     }
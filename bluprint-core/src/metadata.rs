@@ -0,0 +1,67 @@
+//! Information about a map as a whole, rather than about what's in it.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::fmt;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// How much distance one cell stands for, like 5 feet.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct GridUnit {
+    pub amount: f32,
+    pub unit: String,
+}
+
+impl GridUnit {
+    pub fn new(amount: f32, unit: impl Into<String>) -> Self {
+        Self {
+            amount,
+            unit: unit.into(),
+        }
+    }
+}
+
+impl Default for GridUnit {
+    fn default() -> Self {
+        Self::new(5.0, "ft")
+    }
+}
+
+impl fmt::Display for GridUnit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.amount, self.unit)
+    }
+}
+
+/// A map's title, author and so on. Every field is optional in map files.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+#[serde(default)]
+pub struct MapMetadata {
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub title: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub author: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub description: String,
+    /// How far across each cell is.
+    pub grid_unit: GridUnit,
+    /// When the map was first saved.
+    #[serde(with = "crate::format::unix_time", skip_serializing_if = "Option::is_none")]
+    pub created: Option<SystemTime>,
+    /// When the map was last saved.
+    #[serde(with = "crate::format::unix_time", skip_serializing_if = "Option::is_none")]
+    pub modified: Option<SystemTime>,
+    #[serde(skip_serializing_if = "BTreeSet::is_empty")]
+    pub tags: BTreeSet<String>,
+}
+
+impl MapMetadata {
+    /// Marks the map as modified now, and as created now too if it hasn't been saved
+    /// before. Times are rounded down to the second, which is all map files store.
+    pub fn touch(&mut self) {
+        let since_epoch = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        let now = UNIX_EPOCH + Duration::from_secs(since_epoch.as_secs());
+        self.created.get_or_insert(now);
+        self.modified = Some(now);
+    }
+}
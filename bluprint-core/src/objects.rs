@@ -0,0 +1,385 @@
+//! Objects placed freely in a map, rather than in its grid of tiles: props, lights,
+//! creatures and the like.
+
+use crate::tiles::{Color, WorldPos};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+
+/// What an [`ObjectType`] represents, which decides how tools treat its objects.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ObjectKind {
+    /// Scenery, like statues or furniture.
+    #[default]
+    Prop,
+    /// Something that gives off light, like a torch.
+    Light,
+    /// Something that can be opened and hold items, like a chest.
+    Container,
+    /// A token for a creature or character.
+    Creature,
+}
+
+/// A kind of object that can be placed in a map. Every object refers to one of these.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ObjectType {
+    pub id: usize,
+    pub name: String,
+    #[serde(default)]
+    pub kind: ObjectKind,
+    /// The color of the object's marker.
+    #[serde(default)]
+    pub color: Color,
+}
+
+impl ObjectType {
+    pub fn new(id: usize, name: impl Into<String>, kind: ObjectKind) -> Self {
+        Self {
+            id,
+            name: name.into(),
+            kind,
+            color: Color::default(),
+        }
+    }
+}
+
+/// The object types a map can use. Serialized as a list of [`ObjectType`]s.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq, Eq)]
+#[serde(into = "Vec<ObjectType>", try_from = "Vec<ObjectType>")]
+pub struct ObjectSet {
+    types: HashMap<usize, ObjectType>,
+}
+
+impl ObjectSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get_type(&self, id: usize) -> Option<&ObjectType> {
+        self.types.get(&id)
+    }
+
+    pub fn add_type(&mut self, object_type: ObjectType) {
+        self.types.insert(object_type.id, object_type);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.types.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &ObjectType> {
+        self.types.values()
+    }
+}
+
+impl From<ObjectSet> for Vec<ObjectType> {
+    fn from(set: ObjectSet) -> Self {
+        let mut types: Vec<_> = set.types.into_values().collect();
+        types.sort_by_key(|object_type| object_type.id);
+        types
+    }
+}
+
+impl TryFrom<Vec<ObjectType>> for ObjectSet {
+    type Error = String;
+
+    fn try_from(types: Vec<ObjectType>) -> Result<Self, Self::Error> {
+        let mut set = ObjectSet::new();
+        for object_type in types {
+            if set.get_type(object_type.id).is_some() {
+                return Err(format!("duplicate object type {}", object_type.id));
+            }
+            set.add_type(object_type);
+        }
+
+        Ok(set)
+    }
+}
+
+/// Identifies an object within its map. Ids aren't reused while the map is open.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ObjectId(pub u64);
+
+impl ObjectId {
+    /// The largest id. Map files can't use it, because it leaves no id for the next object.
+    pub const MAX: ObjectId = ObjectId(u64::MAX);
+}
+
+impl fmt::Display for ObjectId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{}", self.0)
+    }
+}
+
+/// A position that doesn't have to be on the grid, in the same units as [`WorldPos`]. The
+/// cell at `WorldPos::new(x, y, z)` covers `x..x + 1`, `y..y + 1` and `z..z + 1`.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq)]
+pub struct ObjectPos {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+}
+
+impl ObjectPos {
+    pub fn new(x: f32, y: f32, z: f32) -> Self {
+        Self { x, y, z }
+    }
+
+    /// The cell the position is in.
+    pub fn cell(self) -> WorldPos {
+        WorldPos::new(self.x.floor() as i32, self.y.floor() as i32, self.z.floor() as i32)
+    }
+
+    pub fn distance_to(self, other: ObjectPos) -> f32 {
+        let (dx, dy, dz) = (self.x - other.x, self.y - other.y, self.z - other.z);
+        (dx * dx + dy * dy + dz * dz).sqrt()
+    }
+}
+
+/// The middle of the bottom of a cell, which is where objects placed on it stand.
+impl From<WorldPos> for ObjectPos {
+    fn from(pos: WorldPos) -> Self {
+        Self::new(pos.x as f32 + 0.5, pos.y as f32, pos.z as f32 + 0.5)
+    }
+}
+
+impl From<(f32, f32, f32)> for ObjectPos {
+    fn from((x, y, z): (f32, f32, f32)) -> Self {
+        Self::new(x, y, z)
+    }
+}
+
+/// An object placed in a map.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct MapObject {
+    /// The [`ObjectType`] this is an instance of.
+    pub type_id: usize,
+    pub position: ObjectPos,
+    /// Rotation around the vertical axis in degrees, clockwise when looking down from
+    /// above, the same way as [`Rotation`](crate::tiles::Rotation).
+    #[serde(default)]
+    pub rotation: f32,
+    #[serde(default = "MapObject::default_scale")]
+    pub scale: f32,
+    /// A name for this particular object, like an NPC's name.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+}
+
+impl MapObject {
+    pub fn new(type_id: usize, position: impl Into<ObjectPos>) -> Self {
+        Self {
+            type_id,
+            position: position.into(),
+            rotation: 0.0,
+            scale: Self::default_scale(),
+            name: None,
+        }
+    }
+
+    pub fn with_rotation(self, rotation: f32) -> Self {
+        Self { rotation, ..self }
+    }
+
+    pub fn with_scale(self, scale: f32) -> Self {
+        Self { scale, ..self }
+    }
+
+    pub fn with_name(self, name: impl Into<String>) -> Self {
+        Self {
+            name: Some(name.into()),
+            ..self
+        }
+    }
+
+    fn default_scale() -> f32 {
+        1.0
+    }
+}
+
+/// The width, height and depth in cells of the buckets [`Objects`] sorts objects into.
+const BUCKET_SIZE: i32 = 8;
+
+type Bucket = (i32, i32, i32);
+
+fn bucket(pos: ObjectPos) -> Bucket {
+    let cell = pos.cell();
+    (
+        cell.x.div_floor(BUCKET_SIZE),
+        cell.y.div_floor(BUCKET_SIZE),
+        cell.z.div_floor(BUCKET_SIZE),
+    )
+}
+
+/// The objects in a map, indexed by where they are.
+///
+/// Serialized as a list of objects with their ids, sorted by id.
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+#[serde(into = "Vec<ObjectEntry>", try_from = "Vec<ObjectEntry>")]
+pub struct Objects {
+    objects: HashMap<ObjectId, MapObject>,
+    /// The objects in each bucket, for finding the objects in an area without checking
+    /// every one.
+    buckets: HashMap<Bucket, Vec<ObjectId>>,
+    next_id: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ObjectEntry {
+    id: ObjectId,
+    #[serde(flatten)]
+    object: MapObject,
+}
+
+impl Objects {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.objects.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.objects.is_empty()
+    }
+
+    pub fn get(&self, id: ObjectId) -> Option<&MapObject> {
+        self.objects.get(&id)
+    }
+
+    /// Every object, in no particular order.
+    pub fn iter(&self) -> impl Iterator<Item = (ObjectId, &MapObject)> {
+        self.objects.iter().map(|(id, object)| (*id, object))
+    }
+
+    /// Adds an object, returning its new id.
+    ///
+    /// # Panics
+    ///
+    /// If the ids have run out, like after inserting an object with [`ObjectId::MAX`].
+    pub fn add(&mut self, object: MapObject) -> ObjectId {
+        let id = ObjectId(self.next_id);
+        assert!(!self.objects.contains_key(&id), "no object ids left");
+        self.insert(id, object);
+        id
+    }
+
+    /// Puts an object back under a specific id, replacing any object that has it.
+    pub fn insert(&mut self, id: ObjectId, object: MapObject) -> Option<MapObject> {
+        let old = self.remove(id);
+        self.next_id = self.next_id.max(id.0.saturating_add(1));
+        self.buckets.entry(bucket(object.position)).or_default().push(id);
+        self.objects.insert(id, object);
+        old
+    }
+
+    pub fn remove(&mut self, id: ObjectId) -> Option<MapObject> {
+        let object = self.objects.remove(&id)?;
+        self.unindex(id, bucket(object.position));
+        Some(object)
+    }
+
+    /// Changes an object in place, returning `None` if there's no object with that id.
+    pub fn update<R>(&mut self, id: ObjectId, f: impl FnOnce(&mut MapObject) -> R) -> Option<R> {
+        let object = self.objects.get_mut(&id)?;
+        let old_bucket = bucket(object.position);
+        let result = f(object);
+
+        let new_bucket = bucket(object.position);
+        if new_bucket != old_bucket {
+            self.unindex(id, old_bucket);
+            self.buckets.entry(new_bucket).or_default().push(id);
+        }
+
+        Some(result)
+    }
+
+    fn unindex(&mut self, id: ObjectId, key: Bucket) {
+        if let Some(ids) = self.buckets.get_mut(&key) {
+            ids.retain(|other| *other != id);
+            if ids.is_empty() {
+                self.buckets.remove(&key);
+            }
+        }
+    }
+
+    /// Every object whose position is between `min` and `max` inclusive, in no particular
+    /// order.
+    pub fn in_box(&self, min: impl Into<ObjectPos>, max: impl Into<ObjectPos>) -> impl Iterator<Item = (ObjectId, &MapObject)> {
+        let (min, max) = (min.into(), max.into());
+        let (min_bucket, max_bucket) = (bucket(min), bucket(max));
+
+        self.buckets
+            .iter()
+            .filter(move |((x, y, z), _)| {
+                (min_bucket.0..=max_bucket.0).contains(x)
+                    && (min_bucket.1..=max_bucket.1).contains(y)
+                    && (min_bucket.2..=max_bucket.2).contains(z)
+            })
+            .flat_map(|(_, ids)| ids.iter())
+            .map(move |id| (*id, &self.objects[id]))
+            .filter(move |(_, object)| {
+                let pos = object.position;
+                (min.x..=max.x).contains(&pos.x)
+                    && (min.y..=max.y).contains(&pos.y)
+                    && (min.z..=max.z).contains(&pos.z)
+            })
+    }
+
+    /// Every object within `radius` of `center`, in no particular order.
+    pub fn within(&self, center: impl Into<ObjectPos>, radius: f32) -> impl Iterator<Item = (ObjectId, &MapObject)> {
+        let center = center.into();
+        let min = ObjectPos::new(center.x - radius, center.y - radius, center.z - radius);
+        let max = ObjectPos::new(center.x + radius, center.y + radius, center.z + radius);
+
+        self.in_box(min, max)
+            .filter(move |(_, object)| object.position.distance_to(center) <= radius)
+    }
+
+    /// Every object standing in the cell at `pos`.
+    pub fn in_cell(&self, pos: impl Into<WorldPos>) -> impl Iterator<Item = (ObjectId, &MapObject)> {
+        let pos = pos.into();
+        self.buckets
+            .get(&bucket(ObjectPos::from(pos)))
+            .into_iter()
+            .flatten()
+            .map(move |id| (*id, &self.objects[id]))
+            .filter(move |(_, object)| object.position.cell() == pos)
+    }
+}
+
+/// Two sets of objects are equal if they have the same objects under the same ids, however
+/// they got there.
+impl PartialEq for Objects {
+    fn eq(&self, other: &Self) -> bool {
+        self.objects == other.objects
+    }
+}
+
+impl From<Objects> for Vec<ObjectEntry> {
+    fn from(objects: Objects) -> Self {
+        let mut entries: Vec<_> = objects
+            .objects
+            .into_iter()
+            .map(|(id, object)| ObjectEntry { id, object })
+            .collect();
+        entries.sort_by_key(|entry| entry.id);
+        entries
+    }
+}
+
+impl TryFrom<Vec<ObjectEntry>> for Objects {
+    type Error = String;
+
+    fn try_from(entries: Vec<ObjectEntry>) -> Result<Self, Self::Error> {
+        let mut objects = Objects::new();
+        for entry in entries {
+            if objects.insert(entry.id, entry.object).is_some() {
+                return Err(format!("duplicate object {}", entry.id));
+            }
+        }
+
+        Ok(objects)
+    }
+}
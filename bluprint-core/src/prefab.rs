@@ -0,0 +1,224 @@
+//! Pieces of maps, like rooms and corridors, copied out to be pasted somewhere else.
+//!
+//! Prefabs can be kept in a [`PrefabLibrary`], a directory of prefab files that can be
+//! shared between maps and people.
+
+mod library;
+
+use crate::tiles::{Axis, Bounds, Layer, Rotation, Tile, TileData, WorldPos};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+pub use library::{
+    load_prefab, save_prefab, LibraryListing, LibraryPrefab, PrefabError, PrefabLibrary, Thumbnail, PREFAB_EXTENSION,
+    PREFAB_VERSION,
+};
+
+/// A box of tiles copied out of a map, along with their facings and data.
+///
+/// Positions in a prefab are relative to its lowest corner, so they go from `(0, 0, 0)` up
+/// to one less than its size.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Prefab {
+    size: (u32, u32, u32),
+    cells: Vec<PrefabCell>,
+}
+
+/// A cell of a [`Prefab`] with a tile or data in it.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PrefabCell {
+    pub pos: WorldPos,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tile: Option<Tile>,
+    #[serde(default, skip_serializing_if = "TileData::is_empty")]
+    pub data: TileData,
+}
+
+/// What pasting a [`Prefab`] does to the cells it covers that are empty in the prefab, and
+/// to the data of cells the prefab has no data for.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum PasteMode {
+    /// Leave them as they are, so the prefab is laid over what's already there.
+    #[default]
+    SkipEmpty,
+    /// Empty them and clear their data, so the prefab replaces everything in its box.
+    Overwrite,
+}
+
+/// Where and how to paste a [`Prefab`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PasteOptions {
+    /// Where the lowest corner of the pasted prefab goes.
+    pub at: WorldPos,
+    pub rotation: Rotation,
+    /// The axis to mirror the prefab along, before it's rotated.
+    pub mirror: Option<Axis>,
+    pub mode: PasteMode,
+}
+
+impl PasteOptions {
+    /// Pastes the prefab as it is, with its lowest corner at `at`.
+    pub fn at(at: impl Into<WorldPos>) -> Self {
+        Self {
+            at: at.into(),
+            rotation: Rotation::Deg0,
+            mirror: None,
+            mode: PasteMode::SkipEmpty,
+        }
+    }
+
+    pub fn with_rotation(self, rotation: Rotation) -> Self {
+        Self { rotation, ..self }
+    }
+
+    pub fn with_mirror(self, axis: Axis) -> Self {
+        Self {
+            mirror: Some(axis),
+            ..self
+        }
+    }
+
+    pub fn with_mode(self, mode: PasteMode) -> Self {
+        Self { mode, ..self }
+    }
+}
+
+impl Prefab {
+    /// Copies every tile and cell's data within `bounds` out of a layer.
+    pub fn copy(layer: &Layer, bounds: impl Into<Bounds>) -> Self {
+        let bounds = bounds.into();
+        let min = bounds.min();
+        let relative = |pos: WorldPos| WorldPos::new(pos.x - min.x, pos.y - min.y, pos.z - min.z);
+
+        let mut cells: BTreeMap<WorldPos, PrefabCell> = BTreeMap::new();
+        for (pos, tile) in layer.tiles_in(bounds) {
+            let pos = relative(pos);
+            cells.insert(
+                pos,
+                PrefabCell {
+                    pos,
+                    tile: Some(*tile),
+                    data: TileData::default(),
+                },
+            );
+        }
+        for (pos, data) in layer.tile_data().filter(|(pos, _)| bounds.contains(*pos)) {
+            let pos = relative(pos);
+            cells
+                .entry(pos)
+                .or_insert(PrefabCell {
+                    pos,
+                    tile: None,
+                    data: TileData::default(),
+                })
+                .data = data.clone();
+        }
+
+        Self {
+            size: bounds.size(),
+            cells: cells.into_values().collect(),
+        }
+    }
+
+    /// The number of cells along each axis.
+    pub fn size(&self) -> (u32, u32, u32) {
+        self.size
+    }
+
+    /// The cells with a tile or data in them, ordered by position.
+    pub fn cells(&self) -> &[PrefabCell] {
+        &self.cells
+    }
+
+    /// Whether `pos` is inside the prefab's box.
+    pub fn contains(&self, pos: WorldPos) -> bool {
+        let (x, y, z) = self.size;
+        [(pos.x, x), (pos.y, y), (pos.z, z)]
+            .into_iter()
+            .all(|(coord, len)| coord >= 0 && (coord as u32) < len)
+    }
+
+    /// Whether the prefab has no tiles or data in it.
+    pub fn is_empty(&self) -> bool {
+        self.cells.is_empty()
+    }
+
+    /// The prefab mirrored along `mirror`, and then turned around its `y` axis. Tiles are
+    /// turned and mirrored with it, and its lowest corner stays at `(0, 0, 0)`.
+    pub fn transformed(&self, rotation: Rotation, mirror: Option<Axis>) -> Self {
+        let (width, height, depth) = self.size;
+        let size = match rotation {
+            Rotation::Deg0 | Rotation::Deg180 => (width, height, depth),
+            Rotation::Deg90 | Rotation::Deg270 => (depth, height, width),
+        };
+
+        let mut cells: Vec<PrefabCell> = self
+            .cells
+            .iter()
+            .map(|cell| {
+                let mut pos = cell.pos;
+                let mut tile = cell.tile;
+                if let Some(axis) = mirror {
+                    match axis {
+                        Axis::X => pos.x = width as i32 - 1 - pos.x,
+                        Axis::Y => pos.y = height as i32 - 1 - pos.y,
+                        Axis::Z => pos.z = depth as i32 - 1 - pos.z,
+                    }
+                    tile = tile.map(|tile| tile.mirror(axis));
+                }
+
+                // Each quarter turn takes north (-z) to east (+x), then moves the box back
+                // so it starts at 0 again.
+                let (mut across, mut along) = (width as i32, depth as i32);
+                for _ in 0..rotation.quarter_turns() {
+                    pos = WorldPos::new(along - 1 - pos.z, pos.y, pos.x);
+                    std::mem::swap(&mut across, &mut along);
+                }
+
+                PrefabCell {
+                    pos,
+                    tile: tile.map(|tile| tile.rotate_y(rotation)),
+                    data: cell.data.clone(),
+                }
+            })
+            .collect();
+        cells.sort_by_key(|cell| cell.pos);
+
+        Self { size, cells }
+    }
+
+    /// Pastes the prefab into a layer, returning the box it covers there.
+    pub fn paste(&self, layer: &mut Layer, options: &PasteOptions) -> Bounds {
+        let prefab = self.transformed(options.rotation, options.mirror);
+        let (width, height, depth) = prefab.size;
+        let at = options.at;
+        let bounds = Bounds::new(
+            at,
+            at.offset(width as i32 - 1, height as i32 - 1, depth as i32 - 1),
+        );
+
+        if options.mode == PasteMode::Overwrite {
+            layer.set_tile_range(bounds.min()..=bounds.max(), None);
+            let covered: Vec<WorldPos> = layer
+                .tile_data()
+                .map(|(pos, _)| pos)
+                .filter(|pos| bounds.contains(*pos))
+                .collect();
+            for pos in covered {
+                layer.clear_tile_data(pos);
+            }
+        }
+
+        for cell in prefab.cells {
+            let pos = at.offset(cell.pos.x, cell.pos.y, cell.pos.z);
+            if cell.tile.is_some() {
+                layer.set_tile_at(pos, cell.tile);
+            }
+            if !cell.data.is_empty() {
+                layer.set_tile_data(pos, cell.data);
+            }
+        }
+
+        bounds
+    }
+}
@@ -0,0 +1,390 @@
+use super::Prefab;
+use crate::format::MapError;
+use crate::tiles::{Color, TileSet, TileType, WorldPos};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// The extension of prefab files in a library.
+pub const PREFAB_EXTENSION: &str = "blupf";
+
+/// The version of the prefab file format this version of Bluprint reads and writes.
+pub const PREFAB_VERSION: u32 = 1;
+
+/// An error encountered while reading or writing a prefab file, or saving one to a
+/// [`PrefabLibrary`].
+#[derive(Debug)]
+pub enum PrefabError {
+    /// The file couldn't be read or written, or isn't a prefab file.
+    Map(MapError),
+    /// The file was written by a newer version of Bluprint, or has a version no version of
+    /// Bluprint wrote.
+    UnsupportedVersion(u64),
+    /// A tile refers to a tile type that isn't one of the prefab's tile types, or isn't in
+    /// the tile set it was copied with. `pos` is the cell's position in the prefab.
+    UnknownTile { tile_id: usize, pos: WorldPos },
+    /// A prefab's name has nothing in it that can be used in a file name.
+    InvalidName(String),
+    /// A prefab would be saved in the same file as another prefab, because their names only
+    /// differ in characters that can't be used in file names.
+    NameTaken { name: String, existing: String },
+}
+
+impl fmt::Display for PrefabError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PrefabError::Map(err) => write!(f, "{}", err),
+            PrefabError::UnsupportedVersion(version) => write!(
+                f,
+                "prefab format version {} isn't supported by this version of Bluprint (1 to {})",
+                version, PREFAB_VERSION
+            ),
+            PrefabError::UnknownTile { tile_id, pos } => write!(
+                f,
+                "tile at ({}, {}, {}) has type {}, which isn't one of the prefab's tile types",
+                pos.x, pos.y, pos.z, tile_id
+            ),
+            PrefabError::InvalidName(name) => write!(f, "{:?} can't be used as a prefab name", name),
+            PrefabError::NameTaken { name, existing } => write!(
+                f,
+                "can't save {:?}, because {:?} is already saved in the same file",
+                name, existing
+            ),
+        }
+    }
+}
+
+impl std::error::Error for PrefabError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            PrefabError::Map(err) => Some(err),
+            PrefabError::UnsupportedVersion(_)
+            | PrefabError::UnknownTile { .. }
+            | PrefabError::InvalidName(_)
+            | PrefabError::NameTaken { .. } => None,
+        }
+    }
+}
+
+impl From<MapError> for PrefabError {
+    fn from(err: MapError) -> Self {
+        PrefabError::Map(err)
+    }
+}
+
+impl From<std::io::Error> for PrefabError {
+    fn from(err: std::io::Error) -> Self {
+        PrefabError::Map(err.into())
+    }
+}
+
+impl From<serde_json::Error> for PrefabError {
+    fn from(err: serde_json::Error) -> Self {
+        PrefabError::Map(err.into())
+    }
+}
+
+/// A prefab saved in a [`PrefabLibrary`], with what's needed to find it and to paste it
+/// into other maps.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct LibraryPrefab {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub tags: BTreeSet<String>,
+    /// The types of the prefab's tiles, from the map it was copied out of.
+    pub tile_types: Vec<TileType>,
+    pub thumbnail: Thumbnail,
+    pub prefab: Prefab,
+}
+
+impl LibraryPrefab {
+    /// A prefab copied out of a map using `tile_set`. Fails if one of its tiles has a type
+    /// that isn't in `tile_set`.
+    pub fn new(name: impl Into<String>, prefab: Prefab, tile_set: &TileSet) -> Result<Self, PrefabError> {
+        let mut tile_types = BTreeMap::new();
+        for cell in &prefab.cells {
+            if let Some(tile) = cell.tile {
+                let tile_type = tile_set.get_tile(tile.tile_id).ok_or(PrefabError::UnknownTile {
+                    tile_id: tile.tile_id,
+                    pos: cell.pos,
+                })?;
+                tile_types.insert(tile.tile_id, tile_type.clone());
+            }
+        }
+
+        Ok(Self {
+            name: name.into(),
+            tags: BTreeSet::new(),
+            tile_types: tile_types.into_values().collect(),
+            thumbnail: Thumbnail::new(&prefab, tile_set),
+            prefab,
+        })
+    }
+
+    pub fn with_tag(mut self, tag: impl Into<String>) -> Self {
+        self.tags.insert(tag.into());
+        self
+    }
+
+    /// Whether every word of `query` is in the prefab's name or one of its tags, ignoring
+    /// case. An empty query matches everything.
+    pub fn matches(&self, query: &str) -> bool {
+        let name = self.name.to_lowercase();
+        let tags: Vec<String> = self.tags.iter().map(|tag| tag.to_lowercase()).collect();
+
+        query.split_whitespace().map(str::to_lowercase).all(|word| {
+            name.contains(&word) || tags.iter().any(|tag| tag.contains(&word))
+        })
+    }
+
+    /// The prefab, ready to paste into a map using `tile_set`.
+    ///
+    /// Tiles are matched to the map's tile types by name. Types the map doesn't have are
+    /// added to `tile_set`, keeping their id if it's free. Tiles whose type isn't one of the
+    /// prefab's tile types are left out.
+    pub fn to_prefab(&self, tile_set: &mut TileSet) -> Prefab {
+        let mut ids = BTreeMap::new();
+        for tile_type in &self.tile_types {
+            let existing = tile_set.iter().find(|other| other.name == tile_type.name).map(|other| other.id);
+            let id = existing.unwrap_or_else(|| {
+                let id = if tile_set.get_tile(tile_type.id).is_none() {
+                    tile_type.id
+                } else {
+                    tile_set.iter().map(|other| other.id).max().map_or(0, |max| max + 1)
+                };
+                tile_set.add_tile(id, TileType { id, ..tile_type.clone() });
+                id
+            });
+            ids.insert(tile_type.id, id);
+        }
+
+        let mut prefab = self.prefab.clone();
+        for cell in &mut prefab.cells {
+            cell.tile = cell.tile.and_then(|mut tile| {
+                tile.tile_id = *ids.get(&tile.tile_id)?;
+                Some(tile)
+            });
+        }
+
+        prefab
+    }
+
+    /// Fails if one of the prefab's cells is outside its box, or has a tile whose type isn't
+    /// one of its tile types.
+    fn validate(&self) -> Result<(), PrefabError> {
+        let ids: BTreeSet<usize> = self.tile_types.iter().map(|tile_type| tile_type.id).collect();
+        for cell in self.prefab.cells() {
+            if !self.prefab.contains(cell.pos) {
+                let (x, y, z) = self.prefab.size();
+                return Err(MapError::Corrupt(format!(
+                    "cell at ({}, {}, {}) is outside of the prefab's {}x{}x{} box",
+                    cell.pos.x, cell.pos.y, cell.pos.z, x, y, z
+                ))
+                .into());
+            }
+            if let Some(tile) = cell.tile {
+                if !ids.contains(&tile.tile_id) {
+                    return Err(PrefabError::UnknownTile {
+                        tile_id: tile.tile_id,
+                        pos: cell.pos,
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A small picture of a [`Prefab`] from above, with one pixel for each column of cells
+/// showing the color of the highest tile in it.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Thumbnail {
+    width: u32,
+    depth: u32,
+    /// Rows from north to south, each from west to east. Empty columns are `None`.
+    pixels: Vec<Option<Color>>,
+}
+
+impl Thumbnail {
+    pub fn new(prefab: &Prefab, tile_set: &TileSet) -> Self {
+        let (width, _, depth) = prefab.size();
+        let mut highest: Vec<Option<(i32, Color)>> = vec![None; width as usize * depth as usize];
+        for cell in prefab.cells().iter().filter(|cell| prefab.contains(cell.pos)) {
+            if let Some(tile) = cell.tile {
+                let pixel = &mut highest[cell.pos.z as usize * width as usize + cell.pos.x as usize];
+                if pixel.is_none_or(|(y, _)| cell.pos.y > y) {
+                    let color = tile_set.color(tile.tile_id).unwrap_or_default();
+                    *pixel = Some((cell.pos.y, color));
+                }
+            }
+        }
+
+        Self {
+            width,
+            depth,
+            pixels: highest.into_iter().map(|pixel| pixel.map(|(_, color)| color)).collect(),
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn depth(&self) -> u32 {
+        self.depth
+    }
+
+    /// The color of the column at `x` and `z`, or `None` if it's empty or outside the
+    /// thumbnail.
+    pub fn pixel(&self, x: u32, z: u32) -> Option<Color> {
+        if x >= self.width || z >= self.depth {
+            return None;
+        }
+        self.pixels
+            .get(z as usize * self.width as usize + x as usize)
+            .copied()
+            .flatten()
+    }
+}
+
+#[derive(Serialize)]
+struct VersionedPrefab<'a> {
+    version: u32,
+    #[serde(flatten)]
+    prefab: &'a LibraryPrefab,
+}
+
+/// Reads a prefab file from disk.
+pub fn load_prefab(path: impl AsRef<Path>) -> Result<LibraryPrefab, PrefabError> {
+    let doc: serde_json::Value = serde_json::from_reader(BufReader::new(File::open(path)?))?;
+    let version = doc
+        .get("version")
+        .and_then(serde_json::Value::as_u64)
+        .ok_or_else(|| MapError::Corrupt("missing version".to_owned()))?;
+    if version == 0 || version > PREFAB_VERSION as u64 {
+        return Err(PrefabError::UnsupportedVersion(version));
+    }
+
+    let prefab = LibraryPrefab::deserialize(doc)?;
+    prefab.validate()?;
+    Ok(prefab)
+}
+
+/// Writes a prefab file to disk, replacing anything already at `path`.
+pub fn save_prefab(prefab: &LibraryPrefab, path: impl AsRef<Path>) -> Result<(), PrefabError> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    let doc = VersionedPrefab {
+        version: PREFAB_VERSION,
+        prefab,
+    };
+    serde_json::to_writer(&mut writer, &doc)?;
+    writer.flush()?;
+
+    Ok(())
+}
+
+/// What's in a [`PrefabLibrary`], returned by [`PrefabLibrary::list`].
+#[derive(Debug, Default)]
+pub struct LibraryListing {
+    /// The prefabs that could be read, sorted by name.
+    pub prefabs: Vec<LibraryPrefab>,
+    /// The prefab files that couldn't be read, like corrupt ones or ones saved by a newer
+    /// version of Bluprint, and why.
+    pub failed: Vec<(PathBuf, PrefabError)>,
+}
+
+/// A directory of prefab files, like a shared kit of furniture or rooms.
+///
+/// Each prefab is saved in its own file, named after the prefab.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PrefabLibrary {
+    dir: PathBuf,
+}
+
+impl PrefabLibrary {
+    /// Opens the library in `dir`, creating the directory if it doesn't exist yet.
+    pub fn open(dir: impl Into<PathBuf>) -> Result<Self, PrefabError> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+        Ok(Self { dir })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// The file a prefab called `name` is saved in. Characters that can't safely be used in
+    /// file names are replaced with underscores, so different names can share a file.
+    ///
+    /// Fails with [`PrefabError::InvalidName`] if there'd be nothing but underscores and
+    /// spaces left of the name.
+    pub fn path_of(&self, name: &str) -> Result<PathBuf, PrefabError> {
+        let file_name: String = name
+            .chars()
+            .map(|c| if c.is_alphanumeric() || " -_".contains(c) { c } else { '_' })
+            .collect();
+        if file_name.trim_matches(|c| c == '_' || c == ' ').is_empty() {
+            return Err(PrefabError::InvalidName(name.to_owned()));
+        }
+        Ok(self.dir.join(file_name).with_extension(PREFAB_EXTENSION))
+    }
+
+    /// Every prefab in the library. Files that can't be read are listed separately rather
+    /// than failing the whole listing.
+    pub fn list(&self) -> Result<LibraryListing, PrefabError> {
+        let mut listing = LibraryListing::default();
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|ext| ext == PREFAB_EXTENSION) {
+                match load_prefab(&path) {
+                    Ok(prefab) => listing.prefabs.push(prefab),
+                    Err(err) => listing.failed.push((path, err)),
+                }
+            }
+        }
+        listing.prefabs.sort_by(|a, b| a.name.cmp(&b.name));
+        listing.failed.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+        Ok(listing)
+    }
+
+    /// The prefabs that [match](LibraryPrefab::matches) `query`, and the files that couldn't
+    /// be read.
+    pub fn search(&self, query: &str) -> Result<LibraryListing, PrefabError> {
+        let mut listing = self.list()?;
+        listing.prefabs.retain(|prefab| prefab.matches(query));
+        Ok(listing)
+    }
+
+    pub fn load(&self, name: &str) -> Result<LibraryPrefab, PrefabError> {
+        load_prefab(self.path_of(name)?)
+    }
+
+    /// Saves a prefab, replacing any with the same name.
+    ///
+    /// Fails with [`PrefabError::NameTaken`] if a prefab with a different name is already
+    /// saved in the same file.
+    pub fn save(&self, prefab: &LibraryPrefab) -> Result<(), PrefabError> {
+        let path = self.path_of(&prefab.name)?;
+        if path.exists() {
+            // A file that can't be read can't be told apart, so it's replaced.
+            if let Ok(existing) = load_prefab(&path) {
+                if existing.name != prefab.name {
+                    return Err(PrefabError::NameTaken {
+                        name: prefab.name.clone(),
+                        existing: existing.name,
+                    });
+                }
+            }
+        }
+        save_prefab(prefab, path)
+    }
+
+    pub fn remove(&self, name: &str) -> Result<(), PrefabError> {
+        fs::remove_file(self.path_of(name)?)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,70 @@
+//! Named areas of a map, like the numbered rooms of a dungeon key.
+
+use crate::tiles::{Bounds, WorldPos};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// A named area of a map, made of one or more boxes of cells.
+///
+/// Regions are annotations, and don't affect the map's tiles. They can overlap each other,
+/// and their boxes can overlap too.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Region {
+    pub name: String,
+    pub boxes: Vec<Bounds>,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub description: String,
+    /// Custom key/value fields, like a room's encounter or treasure.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub fields: BTreeMap<String, String>,
+}
+
+impl Region {
+    /// A region with no boxes, which doesn't contain anything until some are added.
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            boxes: Vec::new(),
+            description: String::new(),
+            fields: BTreeMap::new(),
+        }
+    }
+
+    /// Adds a box, from an inclusive range like [`TileMap::set_tile_range`] takes.
+    ///
+    /// [`TileMap::set_tile_range`]: crate::tiles::TileMap::set_tile_range
+    pub fn with_box(mut self, bounds: impl Into<Bounds>) -> Self {
+        self.add_box(bounds);
+        self
+    }
+
+    pub fn with_description(self, description: impl Into<String>) -> Self {
+        Self {
+            description: description.into(),
+            ..self
+        }
+    }
+
+    pub fn with_field(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.fields.insert(key.into(), value.into());
+        self
+    }
+
+    pub fn add_box(&mut self, bounds: impl Into<Bounds>) {
+        self.boxes.push(bounds.into());
+    }
+
+    pub fn field(&self, key: &str) -> Option<&str> {
+        self.fields.get(key).map(String::as_str)
+    }
+
+    pub fn contains(&self, pos: impl Into<WorldPos>) -> bool {
+        let pos = pos.into();
+        self.boxes.iter().any(|bounds| bounds.contains(pos))
+    }
+
+    /// The smallest box containing all of the region's boxes, or `None` if it has none.
+    pub fn bounds(&self) -> Option<Bounds> {
+        self.boxes.iter().copied().reduce(Bounds::union)
+    }
+}
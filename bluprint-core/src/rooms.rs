@@ -0,0 +1,275 @@
+//! Finding the rooms on a floor of a map from its tiles.
+//!
+//! A floor is a single `y` level, the one creatures stand in. A cell on it is open if
+//! nothing solid is in it and something solid is below it to stand on. Open cells that
+//! touch each other north, east, south or west form a room. Rooms are bounded by walls,
+//! which are solid cells and cells with no floor, and by doorways, which join them to the
+//! rooms on the other side.
+//!
+//! Cells are checked on every layer of the map, so walls can be on a different layer to
+//! floors.
+//!
+//! A [`RoomGraph`] of the rooms shows how they connect: which are dead ends, where the
+//! loops are and how to get between them.
+
+mod graph;
+
+use crate::regions::Region;
+use crate::tiles::{Bounds, Direction, TileMap, WorldPos};
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+
+pub use graph::{Connection, RoomGraph};
+
+/// The tag that marks a cell's [`TileData`](crate::tiles::TileData) as a doorway.
+pub const DOOR_TAG: &str = "door";
+
+/// How to find rooms, given to [`detect_rooms`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RoomOptions {
+    /// The `y` level of the floor to look at.
+    pub level: i32,
+    /// Tile types that are doorways, like doors and arches.
+    pub door_types: BTreeSet<usize>,
+    /// Cells with this tag in their data are doorways too, whatever their tile is.
+    pub door_tag: Option<String>,
+}
+
+impl RoomOptions {
+    /// Options for the floor at `level`, where only cells tagged [`DOOR_TAG`] are doorways.
+    pub fn new(level: i32) -> Self {
+        Self {
+            level,
+            door_types: BTreeSet::new(),
+            door_tag: Some(DOOR_TAG.to_owned()),
+        }
+    }
+
+    pub fn with_door_type(mut self, tile_id: usize) -> Self {
+        self.door_types.insert(tile_id);
+        self
+    }
+}
+
+/// A room found by [`detect_rooms`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Room {
+    /// Every open cell in the room, all on the floor's level.
+    pub cells: BTreeSet<WorldPos>,
+    /// The door cells on the room's edge.
+    pub doorways: BTreeSet<WorldPos>,
+    /// The indices of the rooms that share a doorway with this one.
+    pub adjacent: BTreeSet<usize>,
+}
+
+impl Room {
+    /// The number of cells in the room.
+    pub fn area(&self) -> usize {
+        self.cells.len()
+    }
+
+    /// The smallest box containing the whole room.
+    pub fn bounds(&self) -> Bounds {
+        Bounds::enclosing(self.cells.iter().copied()).expect("rooms always have cells")
+    }
+
+    /// A region covering exactly the room's cells.
+    pub fn to_region(&self, name: impl Into<String>) -> Region {
+        let mut region = Region::new(name);
+
+        // Cells are sorted by `x` first, so each run of consecutive `z`s in a column
+        // becomes a box.
+        let mut cells = self.cells.iter().copied().peekable();
+        while let Some(start) = cells.next() {
+            let mut end = start;
+            while let Some(next) = cells.next_if(|next| *next == end.offset(0, 0, 1)) {
+                end = next;
+            }
+            region.add_box(Bounds::new(start, end));
+        }
+
+        region
+    }
+}
+
+/// A doorway between rooms, found by [`detect_rooms`]. Door cells that touch each other
+/// are one doorway, so a door in a thick wall still joins the rooms on either side.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Doorway {
+    /// The first of the doorway's cells next to a room.
+    pub pos: WorldPos,
+    /// Every cell of the doorway.
+    pub cells: BTreeSet<WorldPos>,
+    /// The indices of the rooms the doorway opens onto. Usually two, but a doorway at the
+    /// edge of the map might only open onto one.
+    pub rooms: BTreeSet<usize>,
+}
+
+/// The rooms on one floor of a map, and the doorways between them.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FloorPlan {
+    /// Every room, ordered from north to south, then west to east, by their
+    /// northernmost, then westernmost, cell.
+    pub rooms: Vec<Room>,
+    pub doorways: Vec<Doorway>,
+}
+
+impl FloorPlan {
+    /// The index of the room containing `pos`.
+    pub fn room_at(&self, pos: impl Into<WorldPos>) -> Option<usize> {
+        let pos = pos.into();
+        self.rooms.iter().position(|room| room.cells.contains(&pos))
+    }
+
+    /// Regions for every room, named with the room's number starting from 1, to start a
+    /// dungeon key from.
+    pub fn to_regions(&self) -> Vec<Region> {
+        self.rooms
+            .iter()
+            .enumerate()
+            .map(|(index, room)| room.to_region((index + 1).to_string()))
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Cell {
+    Open,
+    Wall,
+    Door,
+}
+
+fn classify(map: &TileMap, options: &RoomOptions, pos: WorldPos) -> Cell {
+    let tile_set = map.tile_set();
+    let tiles_at = |pos: WorldPos| {
+        map.layers()
+            .iter()
+            .filter_map(move |layer| *layer.get_tile_at(pos)?)
+    };
+
+    let is_door = tiles_at(pos).any(|tile| options.door_types.contains(&tile.tile_id))
+        || options.door_tag.as_deref().is_some_and(|tag| {
+            map.layers()
+                .iter()
+                .filter_map(|layer| layer.get_tile_data(pos))
+                .any(|data| data.has_tag(tag))
+        });
+
+    if is_door {
+        Cell::Door
+    } else if tiles_at(pos).any(|tile| tile_set.is_solid(tile.tile_id)) {
+        Cell::Wall
+    } else if tiles_at(pos.offset(0, -1, 0)).any(|tile| tile_set.is_solid(tile.tile_id)) {
+        Cell::Open
+    } else {
+        Cell::Wall
+    }
+}
+
+/// Finds the rooms on one floor of a map.
+pub fn detect_rooms(map: &TileMap, options: &RoomOptions) -> FloorPlan {
+    let size = map.chunk_size();
+    let below = options.level - 1;
+
+    // Only cells above something solid can be open, so start from every solid tile just
+    // below the floor. Sorting by `z` and then `x` numbers the rooms in reading order.
+    let mut candidates: BTreeSet<(i32, i32)> = BTreeSet::new();
+    for layer in map.layers() {
+        for (cpos, chunk) in layer.chunks() {
+            let origin = cpos.origin(size);
+            if !(origin.y..origin.y + size.y as i32).contains(&below) {
+                continue;
+            }
+
+            for (index, tile) in chunk.tiles().enumerate() {
+                let pos = cpos.world_pos(size, size.pos_at(index));
+                if pos.y == below && tile.is_some_and(|tile| map.tile_set().is_solid(tile.tile_id)) {
+                    candidates.insert((pos.z, pos.x));
+                }
+            }
+        }
+    }
+
+    let mut plan = FloorPlan::default();
+    let mut room_of: BTreeMap<WorldPos, usize> = BTreeMap::new();
+    let mut doors: BTreeMap<WorldPos, BTreeSet<usize>> = BTreeMap::new();
+    let horizontal = [Direction::North, Direction::East, Direction::South, Direction::West];
+
+    for (z, x) in candidates {
+        let seed = WorldPos::new(x, options.level, z);
+        if room_of.contains_key(&seed) || classify(map, options, seed) != Cell::Open {
+            continue;
+        }
+
+        let index = plan.rooms.len();
+        let mut room = Room {
+            cells: BTreeSet::new(),
+            doorways: BTreeSet::new(),
+            adjacent: BTreeSet::new(),
+        };
+
+        let mut queue = VecDeque::from([seed]);
+        room_of.insert(seed, index);
+        while let Some(pos) = queue.pop_front() {
+            room.cells.insert(pos);
+
+            for dir in horizontal {
+                let (dx, dy, dz) = dir.offset();
+                let next = pos.offset(dx, dy, dz);
+                if room_of.contains_key(&next) {
+                    continue;
+                }
+
+                match classify(map, options, next) {
+                    Cell::Open => {
+                        room_of.insert(next, index);
+                        queue.push_back(next);
+                    }
+                    Cell::Door => {
+                        room.doorways.insert(next);
+                        doors.entry(next).or_default().insert(index);
+                    }
+                    Cell::Wall => {}
+                }
+            }
+        }
+
+        plan.rooms.push(room);
+    }
+
+    // Group the door cells next to rooms with every door cell they touch, which finds the
+    // middles of thick doorways.
+    let mut grouped: BTreeSet<WorldPos> = BTreeSet::new();
+    for &pos in doors.keys() {
+        if !grouped.insert(pos) {
+            continue;
+        }
+
+        let mut cells = BTreeSet::new();
+        let mut rooms = BTreeSet::new();
+        let mut queue = VecDeque::from([pos]);
+        while let Some(cell) = queue.pop_front() {
+            cells.insert(cell);
+            rooms.extend(doors.get(&cell).into_iter().flatten().copied());
+
+            for dir in horizontal {
+                let (dx, dy, dz) = dir.offset();
+                let next = cell.offset(dx, dy, dz);
+                if !grouped.contains(&next) && classify(map, options, next) == Cell::Door {
+                    grouped.insert(next);
+                    queue.push_back(next);
+                }
+            }
+        }
+
+        for &a in &rooms {
+            for &b in &rooms {
+                if a != b {
+                    plan.rooms[a].adjacent.insert(b);
+                }
+            }
+        }
+        plan.doorways.push(Doorway { pos, cells, rooms });
+    }
+
+    plan
+}
@@ -0,0 +1,292 @@
+use super::{detect_rooms, FloorPlan, RoomOptions};
+use crate::tiles::{TileMap, WorldPos};
+use std::collections::VecDeque;
+use std::fmt::Write;
+
+/// A way between two rooms.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Connection {
+    pub a: usize,
+    pub b: usize,
+    /// The doorway the connection goes through, if it came from one.
+    pub doorway: Option<WorldPos>,
+}
+
+/// Which rooms of a map lead to which, for looking at how a dungeon flows.
+///
+/// Rooms are numbered from 0. Two rooms can be joined more than once, like a room with two
+/// doors into the same corridor, and that counts as a loop.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RoomGraph {
+    names: Vec<String>,
+    connections: Vec<Connection>,
+    /// The indices into `connections` of each room's connections.
+    adjacency: Vec<Vec<usize>>,
+}
+
+impl RoomGraph {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The graph of a floor plan's rooms, named the same way as
+    /// [`FloorPlan::to_regions`]. Doorways that open onto more than two rooms join each
+    /// pair of them.
+    pub fn from_floor_plan(plan: &FloorPlan) -> Self {
+        let mut graph = Self::new();
+        for index in 0..plan.rooms.len() {
+            graph.add_room((index + 1).to_string());
+        }
+
+        for doorway in &plan.doorways {
+            let rooms: Vec<usize> = doorway.rooms.iter().copied().collect();
+            for (i, &a) in rooms.iter().enumerate() {
+                for &b in &rooms[i + 1..] {
+                    graph.add_connection(Connection {
+                        a,
+                        b,
+                        doorway: Some(doorway.pos),
+                    });
+                }
+            }
+        }
+
+        graph
+    }
+
+    /// Detects the rooms on one floor of a map, and builds their graph.
+    pub fn from_map(map: &TileMap, options: &RoomOptions) -> Self {
+        Self::from_floor_plan(&detect_rooms(map, options))
+    }
+
+    /// Adds a room with no connections, returning its index.
+    pub fn add_room(&mut self, name: impl Into<String>) -> usize {
+        self.names.push(name.into());
+        self.adjacency.push(Vec::new());
+        self.names.len() - 1
+    }
+
+    /// Joins two rooms.
+    ///
+    /// # Panics
+    ///
+    /// Panics if either room doesn't exist.
+    pub fn connect(&mut self, a: usize, b: usize) {
+        self.add_connection(Connection { a, b, doorway: None });
+    }
+
+    fn add_connection(&mut self, connection: Connection) {
+        let index = self.connections.len();
+        self.adjacency[connection.a].push(index);
+        if connection.b != connection.a {
+            self.adjacency[connection.b].push(index);
+        }
+        self.connections.push(connection);
+    }
+
+    pub fn room_count(&self) -> usize {
+        self.names.len()
+    }
+
+    pub fn name(&self, room: usize) -> Option<&str> {
+        self.names.get(room).map(String::as_str)
+    }
+
+    pub fn connections(&self) -> &[Connection] {
+        &self.connections
+    }
+
+    /// The rooms joined to `room`, once for each connection, so a room joined twice is
+    /// listed twice.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the room doesn't exist.
+    pub fn neighbours(&self, room: usize) -> impl Iterator<Item = usize> + '_ {
+        self.adjacency[room].iter().map(move |&index| {
+            let connection = self.connections[index];
+            if connection.a == room {
+                connection.b
+            } else {
+                connection.a
+            }
+        })
+    }
+
+    /// Rooms with exactly one way in or out. Connections from a room back to itself don't
+    /// lead anywhere, so they aren't counted.
+    pub fn dead_ends(&self) -> Vec<usize> {
+        (0..self.room_count())
+            .filter(|&room| self.neighbours(room).filter(|&next| next != room).count() == 1)
+            .collect()
+    }
+
+    /// The number of connections to cross to get from `entrance` to each room, or `None`
+    /// for the rooms that can't be reached.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the entrance doesn't exist.
+    pub fn distances_from(&self, entrance: usize) -> Vec<Option<usize>> {
+        let mut distances = vec![None; self.room_count()];
+        distances[entrance] = Some(0);
+
+        let mut queue = VecDeque::from([entrance]);
+        while let Some(room) = queue.pop_front() {
+            let distance = distances[room].unwrap();
+            for next in self.neighbours(room) {
+                if distances[next].is_none() {
+                    distances[next] = Some(distance + 1);
+                    queue.push_back(next);
+                }
+            }
+        }
+
+        distances
+    }
+
+    /// Rooms that can't be reached from `entrance`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the entrance doesn't exist.
+    pub fn unreachable_from(&self, entrance: usize) -> Vec<usize> {
+        self.distances_from(entrance)
+            .iter()
+            .enumerate()
+            .filter(|(_, distance)| distance.is_none())
+            .map(|(room, _)| room)
+            .collect()
+    }
+
+    /// The shortest route from one room to another, including both, or `None` if there
+    /// isn't one or either room doesn't exist.
+    pub fn shortest_path(&self, from: usize, to: usize) -> Option<Vec<usize>> {
+        if from >= self.room_count() || to >= self.room_count() {
+            return None;
+        }
+
+        let mut previous = vec![None; self.room_count()];
+        let mut visited = vec![false; self.room_count()];
+        visited[from] = true;
+
+        let mut queue = VecDeque::from([from]);
+        while let Some(room) = queue.pop_front() {
+            if room == to {
+                let mut path = vec![to];
+                while let Some(prev) = previous[*path.last().unwrap()] {
+                    path.push(prev);
+                }
+                path.reverse();
+                return Some(path);
+            }
+
+            for next in self.neighbours(room) {
+                if !visited[next] {
+                    visited[next] = true;
+                    previous[next] = Some(room);
+                    queue.push_back(next);
+                }
+            }
+        }
+
+        None
+    }
+
+    /// The independent loops in the graph, each as the rooms around it in order.
+    ///
+    /// Every connection that isn't needed to reach all of the rooms closes exactly one of
+    /// these loops, so a graph with no loops is a tree, where there's only one route
+    /// between any two rooms.
+    pub fn loops(&self) -> Vec<Vec<usize>> {
+        let count = self.room_count();
+        let mut parent: Vec<Option<(usize, usize)>> = vec![None; count];
+        let mut depth = vec![0; count];
+        let mut visited = vec![false; count];
+        let mut in_tree = vec![false; self.connections.len()];
+
+        for root in 0..count {
+            if visited[root] {
+                continue;
+            }
+            visited[root] = true;
+
+            let mut queue = VecDeque::from([root]);
+            while let Some(room) = queue.pop_front() {
+                for &index in &self.adjacency[room] {
+                    let connection = self.connections[index];
+                    let next = if connection.a == room { connection.b } else { connection.a };
+                    if !visited[next] {
+                        visited[next] = true;
+                        in_tree[index] = true;
+                        parent[next] = Some((room, index));
+                        depth[next] = depth[room] + 1;
+                        queue.push_back(next);
+                    }
+                }
+            }
+        }
+
+        let mut loops = Vec::new();
+        for (index, connection) in self.connections.iter().enumerate() {
+            if in_tree[index] {
+                continue;
+            }
+
+            // Walk up from both ends to where their routes back to the root meet.
+            let (mut a, mut b) = (connection.a, connection.b);
+            let (mut from_a, mut from_b) = (vec![a], vec![b]);
+            while a != b {
+                if depth[a] >= depth[b] {
+                    a = parent[a].unwrap().0;
+                    from_a.push(a);
+                } else {
+                    b = parent[b].unwrap().0;
+                    from_b.push(b);
+                }
+            }
+
+            from_b.pop();
+            from_a.extend(from_b.into_iter().rev());
+            loops.push(from_a);
+        }
+
+        loops
+    }
+
+    /// The graph in Graphviz's DOT language. If there's an `entrance`, it's drawn with a
+    /// double outline, and the rooms that can't be reached from it are greyed out. Dead
+    /// ends are drawn as boxes. An `entrance` that doesn't exist is ignored.
+    pub fn to_dot(&self, entrance: Option<usize>) -> String {
+        let entrance = entrance.filter(|&entrance| entrance < self.room_count());
+        let unreachable = entrance.map_or_else(Vec::new, |entrance| self.unreachable_from(entrance));
+        let dead_ends = self.dead_ends();
+
+        let mut dot = String::from("graph rooms {\n");
+        for (room, name) in self.names.iter().enumerate() {
+            let mut attributes = vec![format!("label=\"{}\"", escape(name))];
+            if entrance == Some(room) {
+                attributes.push("peripheries=2".to_owned());
+            }
+            if dead_ends.contains(&room) {
+                attributes.push("shape=box".to_owned());
+            }
+            if unreachable.contains(&room) {
+                attributes.push("style=dashed".to_owned());
+                attributes.push("fontcolor=gray".to_owned());
+                attributes.push("color=gray".to_owned());
+            }
+            writeln!(dot, "    {} [{}];", room, attributes.join(", ")).unwrap();
+        }
+        for connection in &self.connections {
+            writeln!(dot, "    {} -- {};", connection.a, connection.b).unwrap();
+        }
+        dot.push_str("}\n");
+
+        dot
+    }
+}
+
+fn escape(label: &str) -> String {
+    label.replace('\\', "\\\\").replace('"', "\\\"")
+}
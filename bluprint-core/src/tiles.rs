@@ -1,47 +1,85 @@
-use crate::utils::IntoIterator3D;
-use std::collections::HashMap;
+mod chunk;
+mod color;
+mod data;
+mod facing;
+mod flood;
+mod layer;
+mod pos;
+mod shape;
+mod transform;
+
+use crate::metadata::MapMetadata;
+use crate::objects::{ObjectSet, Objects};
+use crate::prefab::{PasteOptions, Prefab};
+use crate::regions::Region;
+use std::collections::{BTreeMap, HashMap};
 use serde::{Serialize, Deserialize};
 
-#[derive(Serialize, Deserialize)]
-pub enum Direction {
-    Up,
-    Down,
-    North,
-    East,
-    South,
-    West,
+pub use chunk::{TileChunk, TileMut};
+pub use color::Color;
+pub use data::TileData;
+pub use facing::{face, Axis, Direction, Facing, InvalidFacing, Rotation};
+pub use flood::{FillTooLarge, FloodFill};
+pub use layer::Layer;
+pub use pos::{Bounds, ChunkPos, ChunkSize, LocalPos, WorldPos};
+pub use shape::Shape;
+
+/// A kind of tile, and the properties shared by every tile of that kind.
+///
+/// Every property is optional in map files, and defaults to what [`TileType::new`] gives.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct TileType {
+    pub id: usize,
+    pub name: String,
+    /// Whether the tile blocks movement. Creatures can't move into a solid tile, but can
+    /// stand on top of one.
+    #[serde(default = "TileType::default_solid")]
+    pub solid: bool,
+    /// Whether the tile blocks line of sight, and hides the faces of tiles behind it.
+    #[serde(default = "TileType::default_opaque")]
+    pub opaque: bool,
+    /// How much it costs to move onto this tile. 1 is normal terrain, and anything higher is
+    /// difficult terrain.
+    #[serde(default = "TileType::default_movement_cost")]
+    pub movement_cost: u32,
+    /// The color the tile is drawn with.
+    #[serde(default)]
+    pub color: Color,
 }
 
-#[derive(Serialize, Deserialize)]
-pub struct Facing(Direction, Direction);
-
-pub mod face {
-    use super::Direction::*;
-    use super::Facing;
+impl TileType {
+    /// A solid, opaque tile type with a movement cost of 1 and the default color.
+    pub fn new(id: usize, name: impl Into<String>) -> Self {
+        Self {
+            id,
+            name: name.into(),
+            solid: Self::default_solid(),
+            opaque: Self::default_opaque(),
+            movement_cost: Self::default_movement_cost(),
+            color: Color::default(),
+        }
+    }
 
-    pub const UP_NORTH: Facing = Facing(Up, North);
-    pub const UP_EAST: Facing = Facing(Up, East);
-    pub const UP_SOUTH: Facing = Facing(Up, South);
-    pub const UP_WEST: Facing = Facing(Up, West);
+    pub fn is_difficult_terrain(&self) -> bool {
+        self.movement_cost > 1
+    }
 
-    pub const DOWN_NORTH: Facing = Facing(Down, North);
-    pub const DOWN_EAST: Facing = Facing(Down, East);
-    pub const DOWN_SOUTH: Facing = Facing(Down, South);
-    pub const DOWN_WEST: Facing = Facing(Down, West);
+    fn default_solid() -> bool {
+        true
+    }
 
-    pub const NORTH_EAST: Facing = Facing(North, East);
-    pub const NORTH_WEST: Facing = Facing(North, West);
-    pub const SOUTH_EAST: Facing = Facing(South, East);
-    pub const SOUTH_WEST: Facing = Facing(South, West);
-}
+    fn default_opaque() -> bool {
+        true
+    }
 
-#[derive(Serialize, Deserialize)]
-pub struct TileType {
-    pub id: usize,
-    pub name: String,
+    fn default_movement_cost() -> u32 {
+        1
+    }
 }
 
-#[derive(Serialize, Deserialize)]
+/// The tile types a map can use. Serialized as a list of [`TileType`]s.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(into = "Vec<TileType>", try_from = "Vec<TileType>")]
 pub struct TileSet {
     tiles: HashMap<usize, TileType>,
 }
@@ -60,181 +98,594 @@ impl TileSet {
     pub fn add_tile(&mut self, id: usize, tile: TileType) {
         self.tiles.insert(id, tile);
     }
+
+    pub fn iter(&self) -> impl Iterator<Item = &TileType> {
+        self.tiles.values()
+    }
+
+    /// Whether tiles of type `id` block movement. Types that aren't in the set don't.
+    pub fn is_solid(&self, id: usize) -> bool {
+        self.get_tile(id).is_some_and(|tile| tile.solid)
+    }
+
+    /// Whether tiles of type `id` block line of sight. Types that aren't in the set don't.
+    pub fn is_opaque(&self, id: usize) -> bool {
+        self.get_tile(id).is_some_and(|tile| tile.opaque)
+    }
+
+    pub fn movement_cost(&self, id: usize) -> Option<u32> {
+        self.get_tile(id).map(|tile| tile.movement_cost)
+    }
+
+    pub fn is_difficult_terrain(&self, id: usize) -> bool {
+        self.get_tile(id).is_some_and(TileType::is_difficult_terrain)
+    }
+
+    pub fn color(&self, id: usize) -> Option<Color> {
+        self.get_tile(id).map(|tile| tile.color)
+    }
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
-pub struct Tile {
-    pub tile_id: usize,
-    // facing: Facing,
+impl From<TileSet> for Vec<TileType> {
+    fn from(tile_set: TileSet) -> Self {
+        let mut tiles: Vec<_> = tile_set.tiles.into_values().collect();
+        tiles.sort_by_key(|tile| tile.id);
+        tiles
+    }
 }
 
-pub const CHUNK_SIZE_X: usize = 16;
-pub const CHUNK_SIZE_Y: usize = 64;
-pub const CHUNK_SIZE_Z: usize = 16;
+impl TryFrom<Vec<TileType>> for TileSet {
+    type Error = String;
+
+    fn try_from(tiles: Vec<TileType>) -> Result<Self, Self::Error> {
+        let mut tile_set = TileSet::new();
+        for tile in tiles {
+            if tile_set.get_tile(tile.id).is_some() {
+                return Err(format!("duplicate tile type {}", tile.id));
+            }
+            tile_set.add_tile(tile.id, tile);
+        }
 
-#[derive(Serialize, Deserialize)]
-pub struct TileChunk {
-    #[serde(flatten)]
-    tiles: Vec<Vec<Vec<Option<Tile>>>>,
+        Ok(tile_set)
+    }
 }
 
-impl TileChunk {
-    pub fn new() -> Self {
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Tile {
+    pub tile_id: usize,
+    #[serde(default, skip_serializing_if = "Facing::is_default")]
+    pub facing: Facing,
+}
+
+impl Tile {
+    /// A tile of the given type, facing up and north.
+    pub fn new(tile_id: usize) -> Self {
         Self {
-            tiles: vec![vec![vec![None; CHUNK_SIZE_X]; CHUNK_SIZE_Y]; CHUNK_SIZE_Z],
+            tile_id,
+            facing: Facing::default(),
         }
     }
 
-    pub fn get_tile(&self, x: usize, y: usize, z: usize) -> &Option<Tile> {
-        &self.tiles[z][y][x]
+    pub fn with_facing(self, facing: Facing) -> Self {
+        Self { facing, ..self }
+    }
+
+    pub fn rotate_y(self, rotation: Rotation) -> Self {
+        self.with_facing(self.facing.rotate_y(rotation))
     }
 
-    pub fn get_tile_mut(&mut self, x: usize, y: usize, z: usize) -> &mut Option<Tile> {
-        &mut self.tiles[z][y][x]
+    pub fn mirror(self, axis: Axis) -> Self {
+        self.with_facing(self.facing.mirror(axis))
     }
 }
 
-#[derive(Serialize, Deserialize)]
+/// A map made of [`Layer`]s of tiles, which all share the map's tile set and chunk size.
+///
+/// A map always has at least one layer. One of them is the active layer, which the map's
+/// own tile and data methods read and write.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(from = "TileMapFields")]
 pub struct TileMap {
-    #[serde(skip)]
-    #[serde(default = "crate::example::tileset")]
+    #[serde(default)]
+    metadata: MapMetadata,
     tile_set: TileSet,
-    chunks: HashMap<(i32, i32, i32), TileChunk>,
+    #[serde(default)]
+    chunk_size: ChunkSize,
+    layers: Vec<Layer>,
+    #[serde(default)]
+    active_layer: usize,
+    #[serde(default, skip_serializing_if = "ObjectSet::is_empty")]
+    object_types: ObjectSet,
+    #[serde(default, skip_serializing_if = "Objects::is_empty")]
+    objects: Objects,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    regions: Vec<Region>,
+}
+
+/// What's actually in a serialized [`TileMap`]. Layers don't serialize their chunk size, so
+/// it's filled in from the map's when converting.
+#[derive(Deserialize)]
+struct TileMapFields {
+    #[serde(default)]
+    metadata: MapMetadata,
+    tile_set: TileSet,
+    #[serde(default)]
+    chunk_size: ChunkSize,
+    layers: Vec<Layer>,
+    #[serde(default)]
+    active_layer: usize,
+    #[serde(default)]
+    object_types: ObjectSet,
+    #[serde(default)]
+    objects: Objects,
+    #[serde(default)]
+    regions: Vec<Region>,
+}
+
+impl From<TileMapFields> for TileMap {
+    fn from(fields: TileMapFields) -> Self {
+        let mut layers = fields.layers;
+        for layer in &mut layers {
+            layer.init_chunk_size(fields.chunk_size);
+        }
+        if layers.is_empty() {
+            layers.push(Layer::new(TileMap::DEFAULT_LAYER_NAME.to_owned(), fields.chunk_size));
+        }
+
+        Self {
+            metadata: fields.metadata,
+            tile_set: fields.tile_set,
+            chunk_size: fields.chunk_size,
+            active_layer: fields.active_layer.min(layers.len() - 1),
+            layers,
+            object_types: fields.object_types,
+            objects: fields.objects,
+            regions: fields.regions,
+        }
+    }
 }
 
 impl TileMap {
+    /// The name of the layer a new map starts with.
+    pub const DEFAULT_LAYER_NAME: &'static str = "Layer 1";
+
     pub fn new(tile_set: TileSet) -> Self {
+        Self::with_chunk_size(tile_set, ChunkSize::DEFAULT)
+    }
+
+    pub fn with_chunk_size(tile_set: TileSet, chunk_size: ChunkSize) -> Self {
         Self {
+            metadata: MapMetadata::default(),
             tile_set,
-            chunks: HashMap::new(),
+            chunk_size,
+            layers: vec![Layer::new(Self::DEFAULT_LAYER_NAME.to_owned(), chunk_size)],
+            active_layer: 0,
+            object_types: ObjectSet::new(),
+            objects: Objects::new(),
+            regions: Vec::new(),
         }
     }
 
-    pub fn get_chunk(&self, cx: i32, cy: i32, cz: i32) -> Option<&TileChunk> {
-        self.chunks.get(&(cx, cy, cz))
+    pub fn metadata(&self) -> &MapMetadata {
+        &self.metadata
     }
 
-    pub fn get_tile_at(&self, x: i32, y: i32, z: i32) -> Option<&Option<Tile>> {
-        let cpos = {
-            let cx = x / CHUNK_SIZE_X as i32;
-            let cy = y / CHUNK_SIZE_Y as i32;
-            let cz = z / CHUNK_SIZE_Z as i32;
+    pub fn metadata_mut(&mut self) -> &mut MapMetadata {
+        &mut self.metadata
+    }
 
-            (cx, cy, cz)
-        };
+    pub fn tile_set(&self) -> &TileSet {
+        &self.tile_set
+    }
 
-        let chunk = self.chunks.get(&cpos)?;
+    pub fn tile_set_mut(&mut self) -> &mut TileSet {
+        &mut self.tile_set
+    }
 
-        let x = (x % CHUNK_SIZE_X as i32) as usize;
-        let y = (y % CHUNK_SIZE_Y as i32) as usize;
-        let z = (z % CHUNK_SIZE_Z as i32) as usize;
+    pub fn chunk_size(&self) -> ChunkSize {
+        self.chunk_size
+    }
 
-        Some(chunk.get_tile(x, y, z))
+    /// The kinds of object that can be placed in the map.
+    pub fn object_types(&self) -> &ObjectSet {
+        &self.object_types
     }
 
-    pub fn get_tile_at_or_create_chunk(&mut self, x: i32, y: i32, z: i32) -> &Option<Tile> {
-        let cpos = {
-            let cx = x / CHUNK_SIZE_X as i32;
-            let cy = y / CHUNK_SIZE_Y as i32;
-            let cz = z / CHUNK_SIZE_Z as i32;
+    pub fn object_types_mut(&mut self) -> &mut ObjectSet {
+        &mut self.object_types
+    }
 
-            (cx, cy, cz)
-        };
+    /// The objects placed in the map, which aren't on any layer.
+    pub fn objects(&self) -> &Objects {
+        &self.objects
+    }
 
-        let chunk = self.chunks.entry(cpos).or_insert(TileChunk::new());
+    pub fn objects_mut(&mut self) -> &mut Objects {
+        &mut self.objects
+    }
 
-        let x = (x % CHUNK_SIZE_X as i32) as usize;
-        let y = (y % CHUNK_SIZE_Y as i32) as usize;
-        let z = (z % CHUNK_SIZE_Z as i32) as usize;
+    /// The map's named regions, in the order they were added.
+    pub fn regions(&self) -> &[Region] {
+        &self.regions
+    }
+
+    pub fn region(&self, index: usize) -> Option<&Region> {
+        self.regions.get(index)
+    }
+
+    pub fn region_mut(&mut self, index: usize) -> Option<&mut Region> {
+        self.regions.get_mut(index)
+    }
 
-        chunk.get_tile(x, y, z)
+    /// Adds a region after the others, returning its index.
+    pub fn add_region(&mut self, region: Region) -> usize {
+        self.regions.push(region);
+        self.regions.len() - 1
     }
 
-    pub fn set_tile_at(&mut self, pos: (i32, i32, i32), tile: Option<Tile>) {
-        let cpos = {
-            let cx = pos.0 / CHUNK_SIZE_X as i32;
-            let cy = pos.1 / CHUNK_SIZE_Y as i32;
-            let cz = pos.2 / CHUNK_SIZE_Z as i32;
+    /// Removes and returns the region at `index`, moving the ones after it down.
+    pub fn remove_region(&mut self, index: usize) -> Option<Region> {
+        (index < self.regions.len()).then(|| self.regions.remove(index))
+    }
+
+    /// The index of the first region called `name`.
+    pub fn find_region(&self, name: &str) -> Option<usize> {
+        self.regions.iter().position(|region| region.name == name)
+    }
+
+    /// Every region that contains `pos`, with its index.
+    pub fn regions_at(&self, pos: impl Into<WorldPos>) -> impl Iterator<Item = (usize, &Region)> {
+        let pos = pos.into();
+        self.regions
+            .iter()
+            .enumerate()
+            .filter(move |(_, region)| region.contains(pos))
+    }
+
+    /// Changes the size of the map's chunks, moving every tile into the new chunk layout.
+    pub fn set_chunk_size(&mut self, chunk_size: ChunkSize) {
+        self.chunk_size = chunk_size;
+        for layer in &mut self.layers {
+            layer.set_chunk_size(chunk_size);
+        }
+    }
+
+    /// Every layer, from the bottom up.
+    pub fn layers(&self) -> &[Layer] {
+        &self.layers
+    }
+
+    pub fn layer(&self, index: usize) -> Option<&Layer> {
+        self.layers.get(index)
+    }
+
+    pub fn layer_mut(&mut self, index: usize) -> Option<&mut Layer> {
+        self.layers.get_mut(index)
+    }
+
+    /// The index of the lowest layer called `name`.
+    pub fn find_layer(&self, name: &str) -> Option<usize> {
+        self.layers.iter().position(|layer| layer.name == name)
+    }
+
+    pub fn active_layer_index(&self) -> usize {
+        self.active_layer
+    }
+
+    pub fn active_layer(&self) -> &Layer {
+        &self.layers[self.active_layer]
+    }
+
+    pub fn active_layer_mut(&mut self) -> &mut Layer {
+        &mut self.layers[self.active_layer]
+    }
 
-            (cx, cy, cz)
+    /// # Panics
+    ///
+    /// Panics if there's no layer at `index`.
+    pub fn set_active_layer(&mut self, index: usize) {
+        assert!(index < self.layers.len(), "no layer at index {}", index);
+        self.active_layer = index;
+    }
+
+    /// Adds an empty layer on top of the others, returning its index.
+    pub fn add_layer(&mut self, name: impl Into<String>) -> usize {
+        self.layers.push(Layer::new(name.into(), self.chunk_size));
+        self.layers.len() - 1
+    }
+
+    /// Removes a layer and returns it, unless it's the map's only layer or there's no layer
+    /// at `index`. If the active layer is removed, the one below it becomes active.
+    pub fn remove_layer(&mut self, index: usize) -> Option<Layer> {
+        if self.layers.len() <= 1 || index >= self.layers.len() {
+            return None;
+        }
+
+        let layer = self.layers.remove(index);
+        if self.active_layer > index || (self.active_layer == index && index > 0) {
+            self.active_layer -= 1;
+        }
+
+        Some(layer)
+    }
+
+    /// Puts a layer back at `index`, like one returned by [`TileMap::remove_layer`], shifting
+    /// the layers above it up. The active layer stays active.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is more than the number of layers.
+    pub fn insert_layer(&mut self, index: usize, mut layer: Layer) {
+        assert!(index <= self.layers.len(), "can't insert a layer at index {}", index);
+
+        layer.set_chunk_size(self.chunk_size);
+        self.layers.insert(index, layer);
+        if self.active_layer >= index {
+            self.active_layer += 1;
+        }
+    }
+
+    /// Moves the layer at `from` so that it ends up at `to`, shifting the layers in
+    /// between. The active layer stays active.
+    ///
+    /// # Panics
+    ///
+    /// Panics if either index is out of range.
+    pub fn move_layer(&mut self, from: usize, to: usize) {
+        assert!(from < self.layers.len(), "no layer at index {}", from);
+        assert!(to < self.layers.len(), "no layer at index {}", to);
+
+        let layer = self.layers.remove(from);
+        self.layers.insert(to, layer);
+
+        self.active_layer = if self.active_layer == from {
+            to
+        } else if from < self.active_layer && self.active_layer <= to {
+            self.active_layer - 1
+        } else if to <= self.active_layer && self.active_layer < from {
+            self.active_layer + 1
+        } else {
+            self.active_layer
         };
+    }
+
+    /// Copies the tiles and data of the layer at `from` onto the layer at `into`, then
+    /// removes `from`. Cells that are empty in `from` keep what `into` had. The active
+    /// layer stays active, or becomes `into` if it was `from`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if either index is out of range, or they're the same.
+    pub fn merge_layers(&mut self, from: usize, into: usize) {
+        assert!(from < self.layers.len(), "no layer at index {}", from);
+        assert!(into < self.layers.len(), "no layer at index {}", into);
+        assert_ne!(from, into, "can't merge a layer into itself");
+
+        let source = self.layers.remove(from);
+        let into = if into > from { into - 1 } else { into };
+        self.layers[into].merge(&source);
+
+        if self.active_layer == from {
+            self.active_layer = into;
+        } else if self.active_layer > from {
+            self.active_layer -= 1;
+        }
+    }
+
+    /// The smallest box containing every tile on every layer, or `None` if the map is
+    /// empty.
+    pub fn bounds(&self) -> Option<Bounds> {
+        self.layers
+            .iter()
+            .filter_map(Layer::bounds)
+            .reduce(Bounds::union)
+    }
+
+    /// The number of occupied cells on every layer. A cell with a tile on two layers is
+    /// counted twice.
+    pub fn occupied_count(&self) -> usize {
+        self.layers.iter().map(Layer::occupied_count).sum()
+    }
+
+    /// The number of tiles of each type on every layer, leaving out types with none.
+    pub fn tile_counts(&self) -> BTreeMap<usize, usize> {
+        let mut counts = BTreeMap::new();
+        for layer in &self.layers {
+            for (tile_id, count) in layer.tile_counts() {
+                *counts.entry(tile_id).or_default() += count;
+            }
+        }
+        counts
+    }
+
+    /// The number of chunks allocated on every layer.
+    pub fn chunk_count(&self) -> usize {
+        self.layers.iter().map(Layer::chunk_count).sum()
+    }
+
+    /// The number of occupied cells in each chunk position, added up over every layer.
+    pub fn chunk_occupancy(&self) -> BTreeMap<ChunkPos, usize> {
+        let mut occupancy = BTreeMap::new();
+        for layer in &self.layers {
+            for (pos, count) in layer.chunk_occupancy() {
+                *occupancy.entry(pos).or_default() += count;
+            }
+        }
+        occupancy
+    }
+
+    /// Copies everything about the map except its tiles.
+    pub(crate) fn clone_without_chunks(&self) -> Self {
+        Self {
+            metadata: self.metadata.clone(),
+            tile_set: self.tile_set.clone(),
+            chunk_size: self.chunk_size,
+            layers: self.layers.iter().map(Layer::clone_without_chunks).collect(),
+            active_layer: self.active_layer,
+            object_types: self.object_types.clone(),
+            objects: self.objects.clone(),
+            regions: self.regions.clone(),
+        }
+    }
+
+    pub fn get_chunk(&self, pos: ChunkPos) -> Option<&TileChunk> {
+        self.active_layer().get_chunk(pos)
+    }
+
+    pub fn chunks(&self) -> impl Iterator<Item = (ChunkPos, &TileChunk)> {
+        self.active_layer().chunks()
+    }
+
+    pub fn get_tile_at(&self, pos: impl Into<WorldPos>) -> Option<&Option<Tile>> {
+        self.active_layer().get_tile_at(pos)
+    }
+
+    /// Every tile on the active layer. Chunks that haven't been allocated are skipped.
+    pub fn tiles(&self) -> impl Iterator<Item = (WorldPos, &Tile)> {
+        self.active_layer().tiles()
+    }
+
+    /// Every tile within `bounds` on the active layer.
+    pub fn tiles_in(&self, bounds: impl Into<Bounds>) -> impl Iterator<Item = (WorldPos, &Tile)> {
+        self.active_layer().tiles_in(bounds)
+    }
 
-        let chunk = self.chunks.entry(cpos).or_insert(TileChunk::new());
+    /// Every tile in the chunk at `pos` on the active layer.
+    pub fn chunk_tiles(&self, pos: ChunkPos) -> impl Iterator<Item = (WorldPos, &Tile)> {
+        self.active_layer().chunk_tiles(pos)
+    }
+
+    /// Calls `f` with every tile on the active layer. See [`Layer::for_each_tile_mut`] for
+    /// why this isn't an iterator.
+    pub fn for_each_tile_mut(&mut self, f: impl FnMut(WorldPos, &mut Option<Tile>)) {
+        self.active_layer_mut().for_each_tile_mut(f)
+    }
+
+    /// Like [`TileMap::for_each_tile_mut`], but only for the tiles within `bounds`.
+    pub fn for_each_tile_in_mut(&mut self, bounds: impl Into<Bounds>, f: impl FnMut(WorldPos, &mut Option<Tile>)) {
+        self.active_layer_mut().for_each_tile_in_mut(bounds, f)
+    }
+
+    /// Like [`TileMap::for_each_tile_mut`], but only for the tiles in the chunk at `pos`.
+    pub fn for_each_chunk_tile_mut(&mut self, pos: ChunkPos, f: impl FnMut(WorldPos, &mut Option<Tile>)) {
+        self.active_layer_mut().for_each_chunk_tile_mut(pos, f)
+    }
+
+    /// Every tile on every layer, with the index of its layer, from the bottom layer up.
+    pub fn all_tiles(&self) -> impl Iterator<Item = (usize, WorldPos, &Tile)> {
+        self.layers.iter().enumerate().flat_map(|(index, layer)| {
+            layer.tiles().map(move |(pos, tile)| (index, pos, tile))
+        })
+    }
 
-        let x = (pos.0 % CHUNK_SIZE_X as i32) as usize;
-        let y = (pos.1 % CHUNK_SIZE_Y as i32) as usize;
-        let z = (pos.2 % CHUNK_SIZE_Z as i32) as usize;
+    /// Every tile within `bounds` on every layer, with the index of its layer.
+    pub fn all_tiles_in(&self, bounds: impl Into<Bounds>) -> impl Iterator<Item = (usize, WorldPos, &Tile)> {
+        let bounds = bounds.into();
+        self.layers.iter().enumerate().flat_map(move |(index, layer)| {
+            layer.tiles_in(bounds).map(move |(pos, tile)| (index, pos, tile))
+        })
+    }
 
-        *chunk.get_tile_mut(x, y, z) = tile;
+    /// Every tile in the chunk at `pos` on every layer, with the index of its layer.
+    pub fn all_chunk_tiles(&self, pos: ChunkPos) -> impl Iterator<Item = (usize, WorldPos, &Tile)> {
+        self.layers.iter().enumerate().flat_map(move |(index, layer)| {
+            layer.chunk_tiles(pos).map(move |(pos, tile)| (index, pos, tile))
+        })
     }
 
-    pub fn set_tile_range(
+    /// Calls `f` with every tile on every layer, and the index of its layer.
+    pub fn for_all_tiles_mut(&mut self, mut f: impl FnMut(usize, WorldPos, &mut Option<Tile>)) {
+        for (index, layer) in self.layers.iter_mut().enumerate() {
+            layer.for_each_tile_mut(|pos, tile| f(index, pos, tile));
+        }
+    }
+
+    /// Like [`TileMap::for_all_tiles_mut`], but only for the tiles within `bounds`.
+    pub fn for_all_tiles_in_mut(
         &mut self,
-        range: std::ops::RangeInclusive<(i32, i32, i32)>,
+        bounds: impl Into<Bounds>,
+        mut f: impl FnMut(usize, WorldPos, &mut Option<Tile>),
+    ) {
+        let bounds = bounds.into();
+        for (index, layer) in self.layers.iter_mut().enumerate() {
+            layer.for_each_tile_in_mut(bounds, |pos, tile| f(index, pos, tile));
+        }
+    }
+
+    /// Like [`TileMap::for_all_tiles_mut`], but only for the tiles in the chunk at `pos`.
+    pub fn for_all_chunk_tiles_mut(&mut self, pos: ChunkPos, mut f: impl FnMut(usize, WorldPos, &mut Option<Tile>)) {
+        for (index, layer) in self.layers.iter_mut().enumerate() {
+            layer.for_each_chunk_tile_mut(pos, |pos, tile| f(index, pos, tile));
+        }
+    }
+
+    pub fn get_tile_at_or_create_chunk(&mut self, pos: impl Into<WorldPos>) -> &Option<Tile> {
+        self.active_layer_mut().get_tile_at_or_create_chunk(pos)
+    }
+
+    /// Sets the tile in a cell. Emptying a cell also removes its [`TileData`].
+    pub fn set_tile_at(&mut self, pos: impl Into<WorldPos>, tile: Option<Tile>) {
+        self.active_layer_mut().set_tile_at(pos, tile)
+    }
+
+    /// Sets every tile in the box with `range.start()` and `range.end()` as opposite
+    /// corners, in any order, creating chunks as needed and removing any left empty.
+    pub fn set_tile_range<P: Into<WorldPos>>(
+        &mut self,
+        range: std::ops::RangeInclusive<P>,
         tile: Option<Tile>,
     ) {
-        let cpos_start = {
-            let cx = range.start().0.div_floor(CHUNK_SIZE_X as i32);
-            let cy = range.start().1.div_floor(CHUNK_SIZE_Y as i32);
-            let cz = range.start().2.div_floor(CHUNK_SIZE_Z as i32);
+        self.active_layer_mut().set_tile_range(range, tile)
+    }
 
-            (cx, cy, cz)
-        };
+    /// Sets every tile in `shape`, creating chunks as needed and removing any left empty.
+    /// See [`Shape`].
+    pub fn fill_shape(&mut self, shape: &Shape, tile: Option<Tile>) {
+        self.active_layer_mut().fill_shape(shape, tile)
+    }
 
-        let cpos_end = {
-            let cx = range.end().0.div_floor(CHUNK_SIZE_X as i32);
-            let cy = range.end().1.div_floor(CHUNK_SIZE_Y as i32);
-            let cz = range.end().2.div_floor(CHUNK_SIZE_Z as i32);
+    /// Flood fills the active layer from `seed`, returning how many cells were changed. See
+    /// [`Layer::flood_fill`].
+    pub fn flood_fill(
+        &mut self,
+        seed: impl Into<WorldPos>,
+        tile: Option<Tile>,
+        options: FloodFill,
+    ) -> Result<usize, FillTooLarge> {
+        self.active_layer_mut().flood_fill(seed, tile, options)
+    }
 
-            (cx, cz, cy)
-        };
+    /// Copies a box of the active layer out into a prefab.
+    pub fn copy_prefab(&self, bounds: impl Into<Bounds>) -> Prefab {
+        Prefab::copy(self.active_layer(), bounds)
+    }
 
-        for (cx, cy, cz) in (cpos_start..=cpos_end).into_3d_iter() {
-            let chunk = self.chunks.entry((cx, cy, cz)).or_insert(TileChunk::new());
-
-            let pos_start = {
-                let x = if range.start().0 < (cx * CHUNK_SIZE_X as i32) {
-                    0
-                } else {
-                    range.start().0.rem_euclid(CHUNK_SIZE_X as i32)
-                } as usize;
-                let y = if range.start().1 < (cy * CHUNK_SIZE_Y as i32) {
-                    0
-                } else {
-                    range.start().1.rem_euclid(CHUNK_SIZE_Y as i32)
-                } as usize;
-                let z = if range.start().2 < (cz * CHUNK_SIZE_Z as i32) {
-                    0
-                } else {
-                    range.start().2.rem_euclid(CHUNK_SIZE_Z as i32)
-                } as usize;
-
-                (x, y, z)
-            };
-
-            let pos_end = {
-                let x = if range.end().0 >= ((cx + 1) * CHUNK_SIZE_X as i32) {
-                    CHUNK_SIZE_X as i32 - 1
-                } else {
-                    range.end().0.rem_euclid(CHUNK_SIZE_X as i32)
-                } as usize;
-                let y = if range.end().1 >= ((cy + 1) * CHUNK_SIZE_Y as i32) {
-                    CHUNK_SIZE_Y as i32 - 1
-                } else {
-                    range.end().1.rem_euclid(CHUNK_SIZE_Y as i32)
-                } as usize;
-                let z = if range.end().2 >= ((cz + 1) * CHUNK_SIZE_Z as i32) {
-                    CHUNK_SIZE_Z as i32 - 1
-                } else {
-                    range.end().2.rem_euclid(CHUNK_SIZE_Z as i32)
-                } as usize;
-
-                (x, y, z)
-            };
-
-            for (x, y, z) in (pos_start..=pos_end).into_3d_iter() {
-                *chunk.get_tile_mut(x, y, z) = tile;
-            }
-        }
+    /// Pastes a prefab into the active layer, returning the box it covers.
+    pub fn paste_prefab(&mut self, prefab: &Prefab, options: &PasteOptions) -> Bounds {
+        prefab.paste(self.active_layer_mut(), options)
+    }
+
+    pub fn get_tile_data(&self, pos: impl Into<WorldPos>) -> Option<&TileData> {
+        self.active_layer().get_tile_data(pos)
+    }
+
+    /// Replaces the data for a cell. Setting empty data removes it.
+    pub fn set_tile_data(&mut self, pos: impl Into<WorldPos>, data: TileData) {
+        self.active_layer_mut().set_tile_data(pos, data)
+    }
+
+    /// Changes the data for a cell in place, starting from empty data if it has none.
+    pub fn update_tile_data<R>(&mut self, pos: impl Into<WorldPos>, f: impl FnOnce(&mut TileData) -> R) -> R {
+        self.active_layer_mut().update_tile_data(pos, f)
+    }
+
+    /// Removes a cell's data, returning what it was.
+    pub fn clear_tile_data(&mut self, pos: impl Into<WorldPos>) -> Option<TileData> {
+        self.active_layer_mut().clear_tile_data(pos)
+    }
+
+    /// Every cell that has data, in no particular order.
+    pub fn tile_data(&self) -> impl Iterator<Item = (WorldPos, &TileData)> {
+        self.active_layer().tile_data()
     }
 }
@@ -0,0 +1,226 @@
+use super::{ChunkSize, LocalPos, Tile};
+use std::collections::HashMap;
+use std::ops::{Deref, DerefMut};
+
+/// A block of cells, with dimensions given by its [`ChunkSize`].
+///
+/// Rather than storing a tile per cell, a chunk keeps a palette of the distinct tiles it
+/// contains, and each cell stores an index into that palette. A chunk with no tiles in it
+/// doesn't allocate any cells at all.
+#[derive(Debug, Clone)]
+pub struct TileChunk {
+    size: ChunkSize,
+    /// Every distinct tile in the chunk. When `cells` is allocated, entry 0 is always the
+    /// empty tile.
+    palette: Vec<Option<Tile>>,
+    /// The number of cells that use each palette entry. Entries with a count of 0 are free
+    /// to be reused.
+    counts: Vec<usize>,
+    /// The palette index of each cell, indexed by [`ChunkSize::index`]. `None` if every
+    /// cell is empty.
+    cells: Option<Box<[u16]>>,
+}
+
+impl TileChunk {
+    /// # Panics
+    ///
+    /// If `size` isn't [valid](ChunkSize::is_valid).
+    pub fn new(size: ChunkSize) -> Self {
+        assert!(size.is_valid(), "invalid chunk size {:?}", size);
+        Self {
+            size,
+            palette: Vec::new(),
+            counts: Vec::new(),
+            cells: None,
+        }
+    }
+
+    pub fn size(&self) -> ChunkSize {
+        self.size
+    }
+
+    pub fn get_tile(&self, pos: LocalPos) -> &Option<Tile> {
+        self.get_index(self.size.index(pos))
+    }
+
+    /// A mutable handle to a cell, which derefs to its `Option<Tile>`. The chunk is updated
+    /// when the handle is dropped.
+    pub fn get_tile_mut(&mut self, pos: LocalPos) -> TileMut<'_> {
+        let index = self.size.index(pos);
+        TileMut {
+            tile: *self.get_index(index),
+            chunk: self,
+            index,
+        }
+    }
+
+    pub fn set_tile(&mut self, pos: LocalPos, tile: Option<Tile>) {
+        self.set_index(self.size.index(pos), tile)
+    }
+
+    /// Whether every cell in the chunk is empty.
+    pub fn is_empty(&self) -> bool {
+        self.cells.is_none()
+    }
+
+    /// The number of cells with a tile in them.
+    pub fn occupied(&self) -> usize {
+        match self.cells {
+            Some(_) => self.size.volume() - self.counts[0],
+            None => 0,
+        }
+    }
+
+    /// Every distinct tile in the chunk, with the number of cells it's in.
+    pub fn tile_counts(&self) -> impl Iterator<Item = (Tile, usize)> + '_ {
+        self.palette
+            .iter()
+            .zip(&self.counts)
+            .filter_map(|(tile, count)| Some(((*tile)?, *count)))
+            .filter(|(_, count)| *count > 0)
+    }
+
+    /// Every cell with a tile in it, in [`ChunkSize::index`] order.
+    pub fn occupied_tiles(&self) -> impl Iterator<Item = (LocalPos, &Tile)> {
+        let size = self.size;
+        let cells = self.cells.as_deref().unwrap_or_default();
+        cells.iter().enumerate().filter_map(move |(index, entry)| {
+            let tile = self.palette[*entry as usize].as_ref()?;
+            Some((size.pos_at(index), tile))
+        })
+    }
+
+    /// Every cell in the chunk, in [`ChunkSize::index`] order.
+    pub fn tiles(&self) -> impl Iterator<Item = &Option<Tile>> {
+        (0..self.size.volume()).map(move |index| self.get_index(index))
+    }
+
+    /// Roughly how many bytes the chunk takes up.
+    pub(crate) fn memory(&self) -> usize {
+        std::mem::size_of::<Self>()
+            + self.palette.len() * (std::mem::size_of::<Option<Tile>>() + std::mem::size_of::<usize>())
+            + self.cells.as_ref().map_or(0, |cells| cells.len() * std::mem::size_of::<u16>())
+    }
+
+    /// The chunk's palette, and the palette index of each cell. `None` if every cell is
+    /// empty.
+    pub(crate) fn palette_cells(&self) -> Option<(&[Option<Tile>], &[u16])> {
+        Some((&self.palette, self.cells.as_deref()?))
+    }
+
+    /// A chunk from a palette and the palette index of each cell, in [`ChunkSize::index`]
+    /// order. The palette can have repeated or unused entries. Returns `None` if an index
+    /// is outside the palette.
+    pub(crate) fn from_palette_cells(size: ChunkSize, palette: &[Option<Tile>], cells: &[u16]) -> Option<Self> {
+        debug_assert_eq!(cells.len(), size.volume());
+
+        let mut chunk = Self::new(size);
+        chunk.palette = vec![None];
+        let mut entries: HashMap<Option<Tile>, u16> = HashMap::from([(None, 0)]);
+        let remap: Vec<u16> = palette
+            .iter()
+            .map(|tile| {
+                *entries.entry(*tile).or_insert_with(|| {
+                    chunk.palette.push(*tile);
+                    (chunk.palette.len() - 1) as u16
+                })
+            })
+            .collect();
+
+        chunk.counts = vec![0; chunk.palette.len()];
+        let mut mapped = Vec::with_capacity(cells.len());
+        for &cell in cells {
+            let entry = *remap.get(cell as usize)?;
+            chunk.counts[entry as usize] += 1;
+            mapped.push(entry);
+        }
+
+        if chunk.counts[0] == size.volume() {
+            return Some(Self::new(size));
+        }
+        chunk.cells = Some(mapped.into_boxed_slice());
+        Some(chunk)
+    }
+
+    fn get_index(&self, index: usize) -> &Option<Tile> {
+        match &self.cells {
+            Some(cells) => &self.palette[cells[index] as usize],
+            None => &None,
+        }
+    }
+
+    fn set_index(&mut self, index: usize, tile: Option<Tile>) {
+        if self.get_index(index) == &tile {
+            return;
+        }
+
+        let len = self.size.volume();
+        let cells = self.cells.get_or_insert_with(|| {
+            self.palette = vec![None];
+            self.counts = vec![len];
+            vec![0; len].into_boxed_slice()
+        });
+
+        let new = match self.palette.iter().position(|entry| *entry == tile) {
+            Some(new) => new,
+            // Reuse an entry that's no longer used before growing the palette.
+            None => match self.counts.iter().skip(1).position(|count| *count == 0) {
+                Some(free) => {
+                    self.palette[free + 1] = tile;
+                    free + 1
+                }
+                None => {
+                    self.palette.push(tile);
+                    self.counts.push(0);
+                    self.palette.len() - 1
+                }
+            },
+        };
+
+        let old = cells[index] as usize;
+        cells[index] = new as u16;
+        self.counts[old] -= 1;
+        self.counts[new] += 1;
+
+        if self.counts[0] == len {
+            *self = Self::new(self.size);
+        }
+    }
+}
+
+impl PartialEq for TileChunk {
+    fn eq(&self, other: &Self) -> bool {
+        // Two chunks can hold the same tiles with differently ordered palettes.
+        self.size == other.size && self.tiles().eq(other.tiles())
+    }
+}
+
+impl Eq for TileChunk {}
+
+/// A mutable handle to one cell of a [`TileChunk`], returned by
+/// [`TileChunk::get_tile_mut`]. Changes are written back to the chunk when it's dropped.
+pub struct TileMut<'a> {
+    chunk: &'a mut TileChunk,
+    index: usize,
+    tile: Option<Tile>,
+}
+
+impl Deref for TileMut<'_> {
+    type Target = Option<Tile>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.tile
+    }
+}
+
+impl DerefMut for TileMut<'_> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.tile
+    }
+}
+
+impl Drop for TileMut<'_> {
+    fn drop(&mut self) {
+        self.chunk.set_index(self.index, self.tile);
+    }
+}
@@ -0,0 +1,79 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// An 8-bit-per-channel RGBA color. Serialized as a hex string, either `"#rrggbb"` or
+/// `"#rrggbbaa"`.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(try_from = "String", into = "String")]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    pub a: u8,
+}
+
+impl Color {
+    pub const WHITE: Color = Color::rgb(255, 255, 255);
+    pub const BLACK: Color = Color::rgb(0, 0, 0);
+    pub const GRAY: Color = Color::rgb(128, 128, 128);
+
+    pub const fn rgb(r: u8, g: u8, b: u8) -> Self {
+        Self::rgba(r, g, b, 255)
+    }
+
+    pub const fn rgba(r: u8, g: u8, b: u8, a: u8) -> Self {
+        Self { r, g, b, a }
+    }
+
+    pub fn is_opaque(self) -> bool {
+        self.a == 255
+    }
+
+    /// Parses a color from `#rrggbb` or `#rrggbbaa`. The leading `#` is optional.
+    pub fn from_hex(hex: &str) -> Option<Self> {
+        let hex = hex.strip_prefix('#').unwrap_or(hex);
+        if !matches!(hex.len(), 6 | 8) || !hex.is_ascii() {
+            return None;
+        }
+
+        let channel = |i: usize| u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).ok();
+        let a = if hex.len() == 8 { channel(3)? } else { 255 };
+
+        Some(Self::rgba(channel(0)?, channel(1)?, channel(2)?, a))
+    }
+
+    /// Formats the color as `#rrggbb`, or `#rrggbbaa` if it isn't fully opaque.
+    pub fn to_hex(self) -> String {
+        self.to_string()
+    }
+}
+
+impl Default for Color {
+    fn default() -> Self {
+        Color::GRAY
+    }
+}
+
+impl fmt::Display for Color {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{:02x}{:02x}{:02x}", self.r, self.g, self.b)?;
+        if !self.is_opaque() {
+            write!(f, "{:02x}", self.a)?;
+        }
+        Ok(())
+    }
+}
+
+impl TryFrom<String> for Color {
+    type Error = String;
+
+    fn try_from(hex: String) -> Result<Self, Self::Error> {
+        Self::from_hex(&hex).ok_or_else(|| format!("invalid color {:?}", hex))
+    }
+}
+
+impl From<Color> for String {
+    fn from(color: Color) -> Self {
+        color.to_hex()
+    }
+}
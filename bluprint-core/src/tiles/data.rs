@@ -0,0 +1,55 @@
+use super::Color;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+
+/// Extra information about a single cell, on top of its [`Tile`](super::Tile).
+///
+/// Most cells don't have any, so maps only store data for the cells that do.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq, Eq)]
+pub struct TileData {
+    /// A color the tile is tinted with, on top of its type's color.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tint: Option<Color>,
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub tags: BTreeSet<String>,
+    /// A note for the GM.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+}
+
+impl TileData {
+    /// Whether there's nothing stored in the data, in which case it doesn't need to be kept.
+    pub fn is_empty(&self) -> bool {
+        self.tint.is_none() && self.tags.is_empty() && self.note.is_none()
+    }
+
+    pub fn with_tint(self, tint: Color) -> Self {
+        Self {
+            tint: Some(tint),
+            ..self
+        }
+    }
+
+    pub fn with_tag(mut self, tag: impl Into<String>) -> Self {
+        self.tags.insert(tag.into());
+        self
+    }
+
+    pub fn with_note(self, note: impl Into<String>) -> Self {
+        Self {
+            note: Some(note.into()),
+            ..self
+        }
+    }
+
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags.contains(tag)
+    }
+
+    /// Roughly how many bytes the data takes up, counting its strings.
+    pub(crate) fn memory(&self) -> usize {
+        std::mem::size_of::<Self>()
+            + self.tags.iter().map(|tag| std::mem::size_of::<String>() + tag.len()).sum::<usize>()
+            + self.note.as_ref().map_or(0, String::len)
+    }
+}
@@ -0,0 +1,289 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// One of the six axis-aligned directions.
+///
+/// North is towards negative `z`, east towards positive `x`, and up towards positive `y`.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Direction {
+    Up,
+    Down,
+    North,
+    East,
+    South,
+    West,
+}
+
+/// A rotation by a multiple of 90°.
+///
+/// Rotations around the `y` axis are clockwise when looking down from above, so a quarter
+/// turn takes north to east.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Rotation {
+    #[default]
+    Deg0,
+    Deg90,
+    Deg180,
+    Deg270,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Axis {
+    X,
+    Y,
+    Z,
+}
+
+/// Which way a tile is turned, given as the direction its top points in followed by the
+/// direction its front faces. The two directions must be perpendicular, which leaves 24
+/// possible orientations.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(try_from = "(Direction, Direction)", into = "(Direction, Direction)")]
+pub struct Facing(Direction, Direction);
+
+/// A [`Facing`] whose directions aren't perpendicular.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InvalidFacing(pub Direction, pub Direction);
+
+pub mod face {
+    use super::Direction::*;
+    use super::Facing;
+
+    pub const UP_NORTH: Facing = Facing(Up, North);
+    pub const UP_EAST: Facing = Facing(Up, East);
+    pub const UP_SOUTH: Facing = Facing(Up, South);
+    pub const UP_WEST: Facing = Facing(Up, West);
+
+    pub const DOWN_NORTH: Facing = Facing(Down, North);
+    pub const DOWN_EAST: Facing = Facing(Down, East);
+    pub const DOWN_SOUTH: Facing = Facing(Down, South);
+    pub const DOWN_WEST: Facing = Facing(Down, West);
+
+    pub const NORTH_UP: Facing = Facing(North, Up);
+    pub const NORTH_DOWN: Facing = Facing(North, Down);
+    pub const NORTH_EAST: Facing = Facing(North, East);
+    pub const NORTH_WEST: Facing = Facing(North, West);
+
+    pub const EAST_UP: Facing = Facing(East, Up);
+    pub const EAST_DOWN: Facing = Facing(East, Down);
+    pub const EAST_NORTH: Facing = Facing(East, North);
+    pub const EAST_SOUTH: Facing = Facing(East, South);
+
+    pub const SOUTH_UP: Facing = Facing(South, Up);
+    pub const SOUTH_DOWN: Facing = Facing(South, Down);
+    pub const SOUTH_EAST: Facing = Facing(South, East);
+    pub const SOUTH_WEST: Facing = Facing(South, West);
+
+    pub const WEST_UP: Facing = Facing(West, Up);
+    pub const WEST_DOWN: Facing = Facing(West, Down);
+    pub const WEST_NORTH: Facing = Facing(West, North);
+    pub const WEST_SOUTH: Facing = Facing(West, South);
+}
+
+impl Direction {
+    pub const ALL: [Direction; 6] = [
+        Direction::Up,
+        Direction::Down,
+        Direction::North,
+        Direction::East,
+        Direction::South,
+        Direction::West,
+    ];
+
+    pub fn opposite(self) -> Self {
+        match self {
+            Direction::Up => Direction::Down,
+            Direction::Down => Direction::Up,
+            Direction::North => Direction::South,
+            Direction::East => Direction::West,
+            Direction::South => Direction::North,
+            Direction::West => Direction::East,
+        }
+    }
+
+    /// The unit step in this direction.
+    pub fn offset(self) -> (i32, i32, i32) {
+        match self {
+            Direction::Up => (0, 1, 0),
+            Direction::Down => (0, -1, 0),
+            Direction::North => (0, 0, -1),
+            Direction::East => (1, 0, 0),
+            Direction::South => (0, 0, 1),
+            Direction::West => (-1, 0, 0),
+        }
+    }
+
+    /// The direction of a unit step, if it is one.
+    pub fn from_offset(offset: (i32, i32, i32)) -> Option<Self> {
+        Self::ALL.into_iter().find(|dir| dir.offset() == offset)
+    }
+
+    pub fn axis(self) -> Axis {
+        match self {
+            Direction::East | Direction::West => Axis::X,
+            Direction::Up | Direction::Down => Axis::Y,
+            Direction::North | Direction::South => Axis::Z,
+        }
+    }
+
+    pub fn is_perpendicular_to(self, other: Direction) -> bool {
+        self.axis() != other.axis()
+    }
+
+    /// Rotates this direction around `axis`. Looking along the axis from its positive end,
+    /// rotations are clockwise, which for `y` matches [`Rotation`].
+    pub fn rotate(self, axis: Axis, rotation: Rotation) -> Self {
+        let mut offset = self.offset();
+        for _ in 0..rotation.quarter_turns() {
+            let (x, y, z) = offset;
+            offset = match axis {
+                Axis::X => (x, z, -y),
+                Axis::Y => (-z, y, x),
+                Axis::Z => (y, -x, z),
+            };
+        }
+
+        Self::from_offset(offset).expect("rotating a unit step gives a unit step")
+    }
+
+    pub fn rotate_y(self, rotation: Rotation) -> Self {
+        self.rotate(Axis::Y, rotation)
+    }
+
+    /// Reflects this direction in the plane perpendicular to `axis`.
+    pub fn mirror(self, axis: Axis) -> Self {
+        if self.axis() == axis {
+            self.opposite()
+        } else {
+            self
+        }
+    }
+}
+
+impl Rotation {
+    pub const ALL: [Rotation; 4] = [
+        Rotation::Deg0,
+        Rotation::Deg90,
+        Rotation::Deg180,
+        Rotation::Deg270,
+    ];
+
+    /// The number of clockwise quarter turns, from 0 to 3.
+    pub fn quarter_turns(self) -> u8 {
+        match self {
+            Rotation::Deg0 => 0,
+            Rotation::Deg90 => 1,
+            Rotation::Deg180 => 2,
+            Rotation::Deg270 => 3,
+        }
+    }
+
+    /// A rotation of `turns` clockwise quarter turns. Negative turns are anticlockwise.
+    pub fn from_quarter_turns(turns: i32) -> Self {
+        Self::ALL[turns.rem_euclid(4) as usize]
+    }
+
+    /// Applying `self`, then `other`.
+    pub fn then(self, other: Rotation) -> Self {
+        Self::from_quarter_turns(self.quarter_turns() as i32 + other.quarter_turns() as i32)
+    }
+
+    pub fn inverse(self) -> Self {
+        Self::from_quarter_turns(-(self.quarter_turns() as i32))
+    }
+}
+
+impl Facing {
+    pub fn new(up: Direction, forward: Direction) -> Result<Self, InvalidFacing> {
+        if up.is_perpendicular_to(forward) {
+            Ok(Self(up, forward))
+        } else {
+            Err(InvalidFacing(up, forward))
+        }
+    }
+
+    /// All 24 orientations, in the same order as [`Facing::index`].
+    pub fn all() -> impl Iterator<Item = Facing> {
+        Direction::ALL.into_iter().flat_map(|up| {
+            Direction::ALL
+                .into_iter()
+                .filter_map(move |forward| Facing::new(up, forward).ok())
+        })
+    }
+
+    /// A number from 0 to 23 that identifies this orientation.
+    pub fn index(self) -> u8 {
+        // Four forward directions go with each up direction, in `Direction::ALL` order,
+        // skipping the two along the same axis as up.
+        let (up, forward) = (self.0 as u8, self.1 as u8);
+        let skipped = Direction::ALL[..forward as usize]
+            .iter()
+            .filter(|dir| dir.axis() == self.0.axis())
+            .count() as u8;
+        up * 4 + forward - skipped
+    }
+
+    pub fn from_index(index: u8) -> Option<Self> {
+        Self::all().nth(index as usize)
+    }
+
+    /// The direction the tile's top points in.
+    pub fn up(self) -> Direction {
+        self.0
+    }
+
+    /// The direction the tile's front faces.
+    pub fn forward(self) -> Direction {
+        self.1
+    }
+
+    pub fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+
+    pub fn rotate(self, axis: Axis, rotation: Rotation) -> Self {
+        Self(self.0.rotate(axis, rotation), self.1.rotate(axis, rotation))
+    }
+
+    pub fn rotate_y(self, rotation: Rotation) -> Self {
+        self.rotate(Axis::Y, rotation)
+    }
+
+    /// Reflects the tile in the plane perpendicular to `axis`. A mirrored tile keeps its
+    /// own handedness, so only the directions it points in change.
+    pub fn mirror(self, axis: Axis) -> Self {
+        Self(self.0.mirror(axis), self.1.mirror(axis))
+    }
+}
+
+impl Default for Facing {
+    fn default() -> Self {
+        face::UP_NORTH
+    }
+}
+
+impl TryFrom<(Direction, Direction)> for Facing {
+    type Error = InvalidFacing;
+
+    fn try_from((up, forward): (Direction, Direction)) -> Result<Self, Self::Error> {
+        Self::new(up, forward)
+    }
+}
+
+impl From<Facing> for (Direction, Direction) {
+    fn from(facing: Facing) -> Self {
+        (facing.0, facing.1)
+    }
+}
+
+impl fmt::Display for InvalidFacing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "a tile can't point {:?} and face {:?}, the directions must be perpendicular",
+            self.0, self.1
+        )
+    }
+}
+
+impl std::error::Error for InvalidFacing {}
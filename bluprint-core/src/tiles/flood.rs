@@ -0,0 +1,130 @@
+use super::{Direction, Layer, Tile, WorldPos};
+use std::collections::{HashSet, VecDeque};
+use std::fmt;
+
+/// How far a flood fill spreads, given to [`TileMap::flood_fill`](super::TileMap::flood_fill).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FloodFill {
+    /// Whether to stay on the seed's `y` level, spreading only north, east, south and west.
+    pub level_only: bool,
+    /// The most cells the fill can change. Empty space goes on forever, so filling an empty
+    /// cell that isn't enclosed would never stop without this.
+    pub max_cells: usize,
+}
+
+impl FloodFill {
+    pub const DEFAULT_MAX_CELLS: usize = 100_000;
+
+    /// A fill that spreads in all six directions.
+    pub fn new() -> Self {
+        Self {
+            level_only: false,
+            max_cells: Self::DEFAULT_MAX_CELLS,
+        }
+    }
+
+    /// A fill that stays on the seed's `y` level.
+    pub fn level() -> Self {
+        Self {
+            level_only: true,
+            ..Self::new()
+        }
+    }
+
+    pub fn with_max_cells(self, max_cells: usize) -> Self {
+        Self { max_cells, ..self }
+    }
+
+    fn directions(self) -> &'static [Direction] {
+        use Direction::*;
+
+        if self.level_only {
+            &[North, East, South, West]
+        } else {
+            &Direction::ALL
+        }
+    }
+}
+
+impl Default for FloodFill {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A flood fill that would have changed more than [`FloodFill::max_cells`] cells. Nothing is
+/// changed when this happens.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FillTooLarge {
+    pub max_cells: usize,
+}
+
+impl fmt::Display for FillTooLarge {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "the fill would change more than {} cells, is the area enclosed?",
+            self.max_cells
+        )
+    }
+}
+
+impl std::error::Error for FillTooLarge {}
+
+impl Layer {
+    /// The cells a flood fill from `seed` would change: the seed and every cell joined to it
+    /// by cells of the same tile type, or by empty cells if the seed is empty. Facing
+    /// doesn't matter.
+    pub fn flood_fill_cells(
+        &self,
+        seed: impl Into<WorldPos>,
+        options: FloodFill,
+    ) -> Result<Vec<WorldPos>, FillTooLarge> {
+        let seed = seed.into();
+        let type_at = |pos: WorldPos| self.get_tile_at(pos).copied().flatten().map(|tile| tile.tile_id);
+        let target = type_at(seed);
+        let too_large = FillTooLarge {
+            max_cells: options.max_cells,
+        };
+        if options.max_cells == 0 {
+            return Err(too_large);
+        }
+
+        let mut cells = vec![seed];
+        let mut visited = HashSet::from([seed]);
+        let mut queue = VecDeque::from([seed]);
+        while let Some(pos) = queue.pop_front() {
+            for dir in options.directions() {
+                let (dx, dy, dz) = dir.offset();
+                let next = pos.offset(dx, dy, dz);
+                if type_at(next) != target || !visited.insert(next) {
+                    continue;
+                }
+
+                if cells.len() == options.max_cells {
+                    return Err(too_large);
+                }
+                cells.push(next);
+                queue.push_back(next);
+            }
+        }
+
+        Ok(cells)
+    }
+
+    /// Replaces the cells [`Layer::flood_fill_cells`] finds with `tile`, returning how many
+    /// there were. If there are too many, nothing is changed.
+    pub fn flood_fill(
+        &mut self,
+        seed: impl Into<WorldPos>,
+        tile: Option<Tile>,
+        options: FloodFill,
+    ) -> Result<usize, FillTooLarge> {
+        let cells = self.flood_fill_cells(seed, options)?;
+        for &pos in &cells {
+            self.set_tile_at(pos, tile);
+        }
+
+        Ok(cells.len())
+    }
+}
@@ -0,0 +1,412 @@
+use super::{Bounds, ChunkPos, ChunkSize, Shape, Tile, TileChunk, TileData, WorldPos};
+use crate::utils::IntoIterator3D;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
+/// One layer of a [`TileMap`](super::TileMap), with its own tiles and per-cell data.
+///
+/// Every layer covers the whole map, so the same cell can hold a different tile on each
+/// layer. Layers are drawn bottom to top, in the order the map keeps them in.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Layer {
+    pub name: String,
+    #[serde(default = "Layer::default_visible")]
+    pub visible: bool,
+    /// Locked layers aren't changed by a [`History`](crate::history::History)'s commands, or
+    /// by undoing or redoing them, so the editor's tools leave them alone. They can still be
+    /// changed directly.
+    #[serde(default)]
+    pub locked: bool,
+    /// Always the same as the map's chunk size, which is where it's serialized.
+    #[serde(skip)]
+    chunk_size: ChunkSize,
+    /// Chunks are shared between clones of a layer until one of them is modified.
+    #[serde(with = "crate::format::chunk_list", default)]
+    chunks: HashMap<ChunkPos, Arc<TileChunk>>,
+    /// Per-cell data, only for the cells that have any.
+    #[serde(with = "crate::format::tile_data_list", default, skip_serializing_if = "HashMap::is_empty")]
+    tile_data: HashMap<WorldPos, TileData>,
+}
+
+impl Layer {
+    pub(super) fn new(name: String, chunk_size: ChunkSize) -> Self {
+        Self {
+            name,
+            visible: true,
+            locked: false,
+            chunk_size,
+            chunks: HashMap::new(),
+            tile_data: HashMap::new(),
+        }
+    }
+
+    fn default_visible() -> bool {
+        true
+    }
+
+    /// Whether the layer has no tiles and no per-cell data.
+    pub fn is_empty(&self) -> bool {
+        self.tile_data.is_empty() && self.chunks.values().all(|chunk| chunk.is_empty())
+    }
+
+    /// Changes the size of the layer's chunks, moving every tile into the new chunk layout.
+    pub(super) fn set_chunk_size(&mut self, chunk_size: ChunkSize) {
+        let old_size = std::mem::replace(&mut self.chunk_size, chunk_size);
+        if old_size == chunk_size {
+            return;
+        }
+
+        for (cpos, chunk) in std::mem::take(&mut self.chunks) {
+            for (index, tile) in chunk.tiles().enumerate() {
+                if tile.is_some() {
+                    self.set_tile_at(cpos.world_pos(old_size, old_size.pos_at(index)), *tile);
+                }
+            }
+        }
+    }
+
+    /// Sets the chunk size of a freshly deserialized layer, whose chunks already have it.
+    pub(super) fn init_chunk_size(&mut self, chunk_size: ChunkSize) {
+        self.chunk_size = chunk_size;
+    }
+
+    /// Copies `other`'s tiles and data over this layer's. Empty cells in `other` leave this
+    /// layer's tiles as they are.
+    pub(super) fn merge(&mut self, other: &Layer) {
+        for (cpos, chunk) in other.chunks() {
+            for (index, tile) in chunk.tiles().enumerate() {
+                if tile.is_some() {
+                    self.set_tile_at(cpos.world_pos(other.chunk_size, other.chunk_size.pos_at(index)), *tile);
+                }
+            }
+        }
+        self.tile_data
+            .extend(other.tile_data.iter().map(|(pos, data)| (*pos, data.clone())));
+    }
+
+    /// The smallest box containing every tile on the layer, or `None` if it has none.
+    pub fn bounds(&self) -> Option<Bounds> {
+        let size = self.chunk_size;
+        self.chunks()
+            .filter(|(_, chunk)| !chunk.is_empty())
+            .flat_map(|(cpos, chunk)| {
+                chunk
+                    .tiles()
+                    .enumerate()
+                    .filter(|(_, tile)| tile.is_some())
+                    .map(move |(index, _)| cpos.world_pos(size, size.pos_at(index)))
+            })
+            .map(Bounds::point)
+            .reduce(Bounds::union)
+    }
+
+    /// The number of cells with a tile in them.
+    pub fn occupied_count(&self) -> usize {
+        self.chunks.values().map(|chunk| chunk.occupied()).sum()
+    }
+
+    /// The number of cells of each tile type, leaving out types with none.
+    pub fn tile_counts(&self) -> BTreeMap<usize, usize> {
+        let mut counts = BTreeMap::new();
+        for chunk in self.chunks.values() {
+            for (tile, count) in chunk.tile_counts() {
+                *counts.entry(tile.tile_id).or_default() += count;
+            }
+        }
+        counts
+    }
+
+    /// The number of chunks that have been allocated. Chunks are removed when they're
+    /// emptied, except ones created by [`Layer::get_tile_at_or_create_chunk`].
+    pub fn chunk_count(&self) -> usize {
+        self.chunks.len()
+    }
+
+    /// The number of occupied cells in each allocated chunk.
+    pub fn chunk_occupancy(&self) -> impl Iterator<Item = (ChunkPos, usize)> + '_ {
+        self.chunks().map(|(pos, chunk)| (pos, chunk.occupied()))
+    }
+
+    /// Every tile on the layer, chunk by chunk. Chunks that haven't been allocated are
+    /// skipped without looking at their cells.
+    pub fn tiles(&self) -> impl Iterator<Item = (WorldPos, &Tile)> {
+        let size = self.chunk_size;
+        self.chunks().flat_map(move |(cpos, chunk)| {
+            chunk
+                .occupied_tiles()
+                .map(move |(local, tile)| (cpos.world_pos(size, local), tile))
+        })
+    }
+
+    /// Every tile within `bounds`, chunk by chunk.
+    pub fn tiles_in(&self, bounds: impl Into<Bounds>) -> impl Iterator<Item = (WorldPos, &Tile)> {
+        let bounds = bounds.into();
+        let size = self.chunk_size;
+        self.chunks()
+            .filter(move |(cpos, _)| bounds.intersects(chunk_bounds(*cpos, size)))
+            .flat_map(move |(cpos, chunk)| {
+                chunk
+                    .occupied_tiles()
+                    .map(move |(local, tile)| (cpos.world_pos(size, local), tile))
+                    .filter(move |(pos, _)| bounds.contains(*pos))
+            })
+    }
+
+    /// Every tile in the chunk at `pos`.
+    pub fn chunk_tiles(&self, pos: ChunkPos) -> impl Iterator<Item = (WorldPos, &Tile)> {
+        let size = self.chunk_size;
+        self.get_chunk(pos).into_iter().flat_map(move |chunk| {
+            chunk
+                .occupied_tiles()
+                .map(move |(local, tile)| (pos.world_pos(size, local), tile))
+        })
+    }
+
+    /// Calls `f` with every tile on the layer. `f` can change the tile or empty the
+    /// cell, which also removes its [`TileData`].
+    ///
+    /// This takes a closure rather than returning an iterator of `&mut Option<Tile>`,
+    /// because cells only hold an index into their chunk's palette. Each change is written
+    /// back to the chunk after `f` returns.
+    pub fn for_each_tile_mut(&mut self, f: impl FnMut(WorldPos, &mut Option<Tile>)) {
+        let chunks: Vec<ChunkPos> = self.chunks.keys().copied().collect();
+        self.update_tiles(chunks, None, f);
+    }
+
+    /// Like [`Layer::for_each_tile_mut`], but only for the tiles within `bounds`.
+    pub fn for_each_tile_in_mut(&mut self, bounds: impl Into<Bounds>, f: impl FnMut(WorldPos, &mut Option<Tile>)) {
+        let bounds = bounds.into();
+        let size = self.chunk_size;
+        let chunks: Vec<ChunkPos> = self
+            .chunks
+            .keys()
+            .copied()
+            .filter(|cpos| bounds.intersects(chunk_bounds(*cpos, size)))
+            .collect();
+        self.update_tiles(chunks, Some(bounds), f);
+    }
+
+    /// Like [`Layer::for_each_tile_mut`], but only for the tiles in the chunk at `pos`.
+    pub fn for_each_chunk_tile_mut(&mut self, pos: ChunkPos, f: impl FnMut(WorldPos, &mut Option<Tile>)) {
+        self.update_tiles(vec![pos], None, f);
+    }
+
+    /// Runs `f` over the occupied cells of some chunks. Chunks are only written to, and so
+    /// only un-shared, if `f` changes one of their tiles.
+    fn update_tiles(
+        &mut self,
+        chunks: Vec<ChunkPos>,
+        bounds: Option<Bounds>,
+        mut f: impl FnMut(WorldPos, &mut Option<Tile>),
+    ) {
+        let size = self.chunk_size;
+        for cpos in chunks {
+            let changes: Vec<(WorldPos, Option<Tile>)> = self
+                .chunk_tiles(cpos)
+                .filter(|(pos, _)| bounds.is_none_or(|bounds| bounds.contains(*pos)))
+                .filter_map(|(pos, tile)| {
+                    let mut new = Some(*tile);
+                    f(pos, &mut new);
+                    (new != Some(*tile)).then_some((pos, new))
+                })
+                .collect();
+
+            for (pos, tile) in changes {
+                debug_assert_eq!(pos.chunk(size), cpos);
+                self.set_tile_at(pos, tile);
+            }
+        }
+    }
+
+    pub fn get_chunk(&self, pos: ChunkPos) -> Option<&TileChunk> {
+        self.chunks.get(&pos).map(|chunk| &**chunk)
+    }
+
+    pub fn chunks(&self) -> impl Iterator<Item = (ChunkPos, &TileChunk)> {
+        self.chunks.iter().map(|(pos, chunk)| (*pos, &**chunk))
+    }
+
+    /// Gets a chunk for writing, creating it if it doesn't exist and un-sharing it if
+    /// it's shared with another layer.
+    fn chunk_mut(&mut self, pos: ChunkPos) -> &mut TileChunk {
+        let size = self.chunk_size;
+        Arc::make_mut(
+            self.chunks
+                .entry(pos)
+                .or_insert_with(|| Arc::new(TileChunk::new(size))),
+        )
+    }
+
+    /// Roughly how many bytes the layer's tiles and data take up.
+    pub(crate) fn memory(&self) -> usize {
+        self.chunks.values().map(|chunk| chunk.memory()).sum::<usize>()
+            + self.tile_data.values().map(TileData::memory).sum::<usize>()
+    }
+
+    /// The layer's chunks, shared rather than copied, to compare against after an edit.
+    pub(crate) fn shared_chunks(&self) -> HashMap<ChunkPos, Arc<TileChunk>> {
+        self.chunks.clone()
+    }
+
+    /// The chunk at `pos`, which is the same allocation as in any clone of the layer until
+    /// one of them modifies it.
+    pub(crate) fn shared_chunk(&self, pos: ChunkPos) -> Option<&Arc<TileChunk>> {
+        self.chunks.get(&pos)
+    }
+
+    pub(crate) fn insert_chunk(&mut self, pos: ChunkPos, chunk: TileChunk) {
+        debug_assert_eq!(chunk.size(), self.chunk_size);
+        self.chunks.insert(pos, Arc::new(chunk));
+    }
+
+    /// Copies everything about the layer except its tiles.
+    pub(crate) fn clone_without_chunks(&self) -> Self {
+        Self {
+            name: self.name.clone(),
+            visible: self.visible,
+            locked: self.locked,
+            chunk_size: self.chunk_size,
+            chunks: HashMap::new(),
+            tile_data: self.tile_data.clone(),
+        }
+    }
+
+    pub fn get_tile_at(&self, pos: impl Into<WorldPos>) -> Option<&Option<Tile>> {
+        let (cpos, local) = pos.into().split(self.chunk_size);
+        let chunk = self.chunks.get(&cpos)?;
+
+        Some(chunk.get_tile(local))
+    }
+
+    pub fn get_tile_at_or_create_chunk(&mut self, pos: impl Into<WorldPos>) -> &Option<Tile> {
+        let size = self.chunk_size;
+        let (cpos, local) = pos.into().split(size);
+        let chunk = self
+            .chunks
+            .entry(cpos)
+            .or_insert_with(|| Arc::new(TileChunk::new(size)));
+
+        chunk.get_tile(local)
+    }
+
+    /// Sets the tile in a cell. Emptying a cell also removes its [`TileData`], and removes
+    /// its chunk if that leaves the chunk empty.
+    pub fn set_tile_at(&mut self, pos: impl Into<WorldPos>, tile: Option<Tile>) {
+        let pos = pos.into();
+        let (cpos, local) = pos.split(self.chunk_size);
+
+        if tile.is_none() {
+            self.tile_data.remove(&pos);
+        }
+        if self.get_tile_at(pos).copied().flatten() == tile {
+            return;
+        }
+
+        let chunk = self.chunk_mut(cpos);
+        chunk.set_tile(local, tile);
+        if chunk.is_empty() {
+            self.chunks.remove(&cpos);
+        }
+    }
+
+    /// Sets every tile in the box with `range.start()` and `range.end()` as opposite
+    /// corners, creating chunks as needed and removing any left empty. Like a [`Bounds`],
+    /// the corners can be in any order, so a range is never empty.
+    pub fn set_tile_range<P: Into<WorldPos>>(
+        &mut self,
+        range: std::ops::RangeInclusive<P>,
+        tile: Option<Tile>,
+    ) {
+        let bounds = Bounds::from(range);
+        let (start, end) = (bounds.min(), bounds.max());
+
+        let size = self.chunk_size;
+        let cpos_range = start.chunk(size).into()..=end.chunk(size).into();
+        for cpos in cpos_range.into_3d_iter() {
+            let cpos = ChunkPos::from(cpos);
+            if tile.is_none() && !self.chunks.contains_key(&cpos) {
+                continue;
+            }
+            let origin = cpos.origin(size);
+            let chunk = self.chunk_mut(cpos);
+
+            let pos_start = (
+                (start.x - origin.x).max(0) as usize,
+                (start.y - origin.y).max(0) as usize,
+                (start.z - origin.z).max(0) as usize,
+            );
+
+            let pos_end = (
+                (end.x - origin.x).min(size.x as i32 - 1) as usize,
+                (end.y - origin.y).min(size.y as i32 - 1) as usize,
+                (end.z - origin.z).min(size.z as i32 - 1) as usize,
+            );
+
+            for local in (pos_start..=pos_end).into_3d_iter() {
+                chunk.set_tile(local.into(), tile);
+            }
+            if chunk.is_empty() {
+                self.chunks.remove(&cpos);
+            }
+        }
+
+        if tile.is_none() {
+            self.tile_data.retain(|pos, _| !bounds.contains(*pos));
+        }
+    }
+
+    /// Sets every tile in `shape`, creating chunks as needed and removing any left empty.
+    pub fn fill_shape(&mut self, shape: &Shape, tile: Option<Tile>) {
+        if let Shape::Box(bounds) = *shape {
+            return self.set_tile_range(bounds.min()..=bounds.max(), tile);
+        }
+
+        for pos in shape.positions() {
+            self.set_tile_at(pos, tile);
+        }
+    }
+
+    pub fn get_tile_data(&self, pos: impl Into<WorldPos>) -> Option<&TileData> {
+        self.tile_data.get(&pos.into())
+    }
+
+    /// Replaces the data for a cell. Setting empty data removes it.
+    pub fn set_tile_data(&mut self, pos: impl Into<WorldPos>, data: TileData) {
+        let pos = pos.into();
+        if data.is_empty() {
+            self.tile_data.remove(&pos);
+        } else {
+            self.tile_data.insert(pos, data);
+        }
+    }
+
+    /// Changes the data for a cell in place, starting from empty data if it has none.
+    pub fn update_tile_data<R>(&mut self, pos: impl Into<WorldPos>, f: impl FnOnce(&mut TileData) -> R) -> R {
+        let pos = pos.into();
+        let mut data = self.tile_data.remove(&pos).unwrap_or_default();
+        let result = f(&mut data);
+        self.set_tile_data(pos, data);
+
+        result
+    }
+
+    /// Removes a cell's data, returning what it was.
+    pub fn clear_tile_data(&mut self, pos: impl Into<WorldPos>) -> Option<TileData> {
+        self.tile_data.remove(&pos.into())
+    }
+
+    /// Every cell that has data, in no particular order.
+    pub fn tile_data(&self) -> impl Iterator<Item = (WorldPos, &TileData)> {
+        self.tile_data.iter().map(|(pos, data)| (*pos, data))
+    }
+}
+
+/// The cells covered by the chunk at `pos`.
+fn chunk_bounds(pos: ChunkPos, size: ChunkSize) -> Bounds {
+    let origin = pos.origin(size);
+    Bounds::new(
+        origin,
+        origin.offset(size.x as i32 - 1, size.y as i32 - 1, size.z as i32 - 1),
+    )
+}
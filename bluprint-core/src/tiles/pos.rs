@@ -0,0 +1,336 @@
+use serde::{Deserialize, Serialize};
+
+/// The dimensions of the chunks in a map, in tiles.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ChunkSize {
+    pub x: usize,
+    pub y: usize,
+    pub z: usize,
+}
+
+/// The position of a single tile in the world.
+///
+/// World positions are unbounded in every direction. Use [`WorldPos::chunk`] and
+/// [`WorldPos::local`] to find where the tile is stored; both round towards negative
+/// infinity, so with 16 tile wide chunks `x = -1` lives at local `x = 15` of chunk
+/// `x = -1`.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct WorldPos {
+    pub x: i32,
+    pub y: i32,
+    pub z: i32,
+}
+
+/// The position of a chunk, measured in whole chunks.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ChunkPos {
+    pub x: i32,
+    pub y: i32,
+    pub z: i32,
+}
+
+/// The position of a tile within its chunk.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct LocalPos {
+    pub x: usize,
+    pub y: usize,
+    pub z: usize,
+}
+
+/// An axis-aligned box of cells, including both corners. A box always has at least one
+/// cell in it.
+///
+/// Serialized as its `min` and `max` corners. Corners that are the wrong way round are
+/// swapped when deserializing.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(from = "BoundsCorners")]
+pub struct Bounds {
+    min: WorldPos,
+    max: WorldPos,
+}
+
+#[derive(Deserialize)]
+struct BoundsCorners {
+    min: WorldPos,
+    max: WorldPos,
+}
+
+impl ChunkSize {
+    pub const DEFAULT: ChunkSize = ChunkSize::new(16, 64, 16);
+
+    /// The most cells a chunk can have. Chunks store each cell as a `u16` index into a
+    /// palette that always has the empty tile in it, so this is one less than the number
+    /// of indices.
+    pub const MAX_VOLUME: usize = u16::MAX as usize;
+
+    /// # Panics
+    ///
+    /// If any dimension is 0, or the chunk would have more than [`ChunkSize::MAX_VOLUME`]
+    /// cells.
+    pub const fn new(x: usize, y: usize, z: usize) -> Self {
+        assert!(x > 0 && y > 0 && z > 0, "chunk dimensions must be non-zero");
+        let size = Self { x, y, z };
+        assert!(size.is_valid(), "chunks can't have more than ChunkSize::MAX_VOLUME cells");
+        size
+    }
+
+    /// Whether no dimension is 0, and the chunk has at most [`ChunkSize::MAX_VOLUME`]
+    /// cells. The fields are public, so a size read from a file might not be.
+    pub const fn is_valid(self) -> bool {
+        let volume = match self.x.checked_mul(self.y) {
+            Some(area) => area.checked_mul(self.z),
+            None => None,
+        };
+        match volume {
+            Some(volume) => volume > 0 && volume <= Self::MAX_VOLUME,
+            None => false,
+        }
+    }
+
+    /// The number of cells in a chunk.
+    pub fn volume(self) -> usize {
+        self.x * self.y * self.z
+    }
+
+    pub fn contains(self, pos: LocalPos) -> bool {
+        pos.x < self.x && pos.y < self.y && pos.z < self.z
+    }
+
+    /// The position of a cell in a chunk's storage. Cells are ordered with `x` varying
+    /// fastest, then `y`, then `z`.
+    pub fn index(self, pos: LocalPos) -> usize {
+        debug_assert!(self.contains(pos));
+        pos.x + self.x * (pos.y + self.y * pos.z)
+    }
+
+    /// The inverse of [`ChunkSize::index`].
+    pub fn pos_at(self, index: usize) -> LocalPos {
+        LocalPos::new(
+            index % self.x,
+            (index / self.x) % self.y,
+            index / (self.x * self.y),
+        )
+    }
+
+    /// Every position in a chunk, in [`ChunkSize::index`] order.
+    pub fn positions(self) -> impl Iterator<Item = LocalPos> {
+        (0..self.volume()).map(move |index| self.pos_at(index))
+    }
+}
+
+impl Default for ChunkSize {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+impl WorldPos {
+    pub const fn new(x: i32, y: i32, z: i32) -> Self {
+        Self { x, y, z }
+    }
+
+    pub fn offset(self, dx: i32, dy: i32, dz: i32) -> Self {
+        Self::new(self.x + dx, self.y + dy, self.z + dz)
+    }
+
+    /// The chunk that contains this position.
+    pub fn chunk(self, size: ChunkSize) -> ChunkPos {
+        ChunkPos::new(
+            self.x.div_floor(size.x as i32),
+            self.y.div_floor(size.y as i32),
+            self.z.div_floor(size.z as i32),
+        )
+    }
+
+    /// This position relative to the origin of its chunk.
+    pub fn local(self, size: ChunkSize) -> LocalPos {
+        LocalPos::new(
+            self.x.rem_euclid(size.x as i32) as usize,
+            self.y.rem_euclid(size.y as i32) as usize,
+            self.z.rem_euclid(size.z as i32) as usize,
+        )
+    }
+
+    pub fn split(self, size: ChunkSize) -> (ChunkPos, LocalPos) {
+        (self.chunk(size), self.local(size))
+    }
+}
+
+impl ChunkPos {
+    pub const fn new(x: i32, y: i32, z: i32) -> Self {
+        Self { x, y, z }
+    }
+
+    /// The world position of the chunk's `(0, 0, 0)` tile.
+    pub fn origin(self, size: ChunkSize) -> WorldPos {
+        WorldPos::new(
+            self.x * size.x as i32,
+            self.y * size.y as i32,
+            self.z * size.z as i32,
+        )
+    }
+
+    /// The world position of a tile within this chunk. This is the inverse of
+    /// [`WorldPos::split`].
+    pub fn world_pos(self, size: ChunkSize, local: LocalPos) -> WorldPos {
+        self.origin(size)
+            .offset(local.x as i32, local.y as i32, local.z as i32)
+    }
+}
+
+impl Bounds {
+    /// The box with `a` and `b` as opposite corners, in any order.
+    pub fn new(a: impl Into<WorldPos>, b: impl Into<WorldPos>) -> Self {
+        let (a, b) = (a.into(), b.into());
+        Self {
+            min: WorldPos::new(a.x.min(b.x), a.y.min(b.y), a.z.min(b.z)),
+            max: WorldPos::new(a.x.max(b.x), a.y.max(b.y), a.z.max(b.z)),
+        }
+    }
+
+    /// The box containing only `pos`.
+    pub fn point(pos: impl Into<WorldPos>) -> Self {
+        let pos = pos.into();
+        Self { min: pos, max: pos }
+    }
+
+    /// The smallest box containing every position, or `None` if there aren't any.
+    pub fn enclosing(positions: impl IntoIterator<Item = WorldPos>) -> Option<Self> {
+        positions
+            .into_iter()
+            .map(Bounds::point)
+            .reduce(Bounds::union)
+    }
+
+    pub fn min(self) -> WorldPos {
+        self.min
+    }
+
+    pub fn max(self) -> WorldPos {
+        self.max
+    }
+
+    /// The number of cells along each axis.
+    pub fn size(self) -> (u32, u32, u32) {
+        (
+            self.max.x.abs_diff(self.min.x) + 1,
+            self.max.y.abs_diff(self.min.y) + 1,
+            self.max.z.abs_diff(self.min.z) + 1,
+        )
+    }
+
+    /// The number of cells in the box.
+    pub fn volume(self) -> u64 {
+        let (x, y, z) = self.size();
+        x as u64 * y as u64 * z as u64
+    }
+
+    pub fn contains(self, pos: impl Into<WorldPos>) -> bool {
+        let pos = pos.into();
+        (self.min.x..=self.max.x).contains(&pos.x)
+            && (self.min.y..=self.max.y).contains(&pos.y)
+            && (self.min.z..=self.max.z).contains(&pos.z)
+    }
+
+    pub fn intersects(self, other: Bounds) -> bool {
+        self.intersection(other).is_some()
+    }
+
+    /// The smallest box containing both boxes.
+    pub fn union(self, other: Bounds) -> Self {
+        Self {
+            min: WorldPos::new(self.min.x.min(other.min.x), self.min.y.min(other.min.y), self.min.z.min(other.min.z)),
+            max: WorldPos::new(self.max.x.max(other.max.x), self.max.y.max(other.max.y), self.max.z.max(other.max.z)),
+        }
+    }
+
+    /// The cells in both boxes, if there are any.
+    pub fn intersection(self, other: Bounds) -> Option<Self> {
+        let min = WorldPos::new(self.min.x.max(other.min.x), self.min.y.max(other.min.y), self.min.z.max(other.min.z));
+        let max = WorldPos::new(self.max.x.min(other.max.x), self.max.y.min(other.max.y), self.max.z.min(other.max.z));
+
+        (min.x <= max.x && min.y <= max.y && min.z <= max.z).then_some(Self { min, max })
+    }
+
+    /// The box moved by the given offset.
+    pub fn offset(self, dx: i32, dy: i32, dz: i32) -> Self {
+        Self {
+            min: self.min.offset(dx, dy, dz),
+            max: self.max.offset(dx, dy, dz),
+        }
+    }
+
+    /// Every position in the box, with `x` varying fastest, then `y`, then `z`.
+    pub fn positions(self) -> impl Iterator<Item = WorldPos> {
+        (self.min.z..=self.max.z).flat_map(move |z| {
+            (self.min.y..=self.max.y)
+                .flat_map(move |y| (self.min.x..=self.max.x).map(move |x| WorldPos::new(x, y, z)))
+        })
+    }
+
+    /// Every chunk that overlaps the box.
+    pub fn chunks(self, size: ChunkSize) -> impl Iterator<Item = ChunkPos> {
+        let (min, max) = (self.min.chunk(size), self.max.chunk(size));
+        (min.z..=max.z).flat_map(move |z| {
+            (min.y..=max.y).flat_map(move |y| (min.x..=max.x).map(move |x| ChunkPos::new(x, y, z)))
+        })
+    }
+}
+
+impl From<BoundsCorners> for Bounds {
+    fn from(corners: BoundsCorners) -> Self {
+        Self::new(corners.min, corners.max)
+    }
+}
+
+/// The box with the range's ends as opposite corners. Ends that are the wrong way round
+/// are swapped rather than giving an empty box, the same as in
+/// [`Layer::set_tile_range`](super::Layer::set_tile_range).
+impl<P: Into<WorldPos>> From<std::ops::RangeInclusive<P>> for Bounds {
+    fn from(range: std::ops::RangeInclusive<P>) -> Self {
+        let (start, end) = range.into_inner();
+        Self::new(start, end)
+    }
+}
+
+impl LocalPos {
+    pub const fn new(x: usize, y: usize, z: usize) -> Self {
+        Self { x, y, z }
+    }
+}
+
+impl From<(i32, i32, i32)> for WorldPos {
+    fn from((x, y, z): (i32, i32, i32)) -> Self {
+        Self::new(x, y, z)
+    }
+}
+
+impl From<WorldPos> for (i32, i32, i32) {
+    fn from(pos: WorldPos) -> Self {
+        (pos.x, pos.y, pos.z)
+    }
+}
+
+impl From<(i32, i32, i32)> for ChunkPos {
+    fn from((x, y, z): (i32, i32, i32)) -> Self {
+        Self::new(x, y, z)
+    }
+}
+
+impl From<ChunkPos> for (i32, i32, i32) {
+    fn from(pos: ChunkPos) -> Self {
+        (pos.x, pos.y, pos.z)
+    }
+}
+
+impl From<(usize, usize, usize)> for LocalPos {
+    fn from((x, y, z): (usize, usize, usize)) -> Self {
+        Self::new(x, y, z)
+    }
+}
+
+impl From<LocalPos> for (usize, usize, usize) {
+    fn from(pos: LocalPos) -> Self {
+        (pos.x, pos.y, pos.z)
+    }
+}
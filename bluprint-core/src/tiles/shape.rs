@@ -0,0 +1,166 @@
+use super::{Axis, Bounds, Direction, WorldPos};
+
+/// A set of cells to fill at once with [`TileMap::fill_shape`].
+///
+/// Curved shapes are fitted to their box, so an ellipsoid touches the middle of each of
+/// its box's faces, and one in a cube is a sphere. Hollow shapes keep only the cells on
+/// their surface, one cell thick.
+///
+/// [`TileMap::fill_shape`]: super::TileMap::fill_shape
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Shape {
+    /// Every cell in the box.
+    Box(Bounds),
+    /// The walls, floor and ceiling of the box, like a closed room.
+    HollowBox(Bounds),
+    /// The four sides of the box, with no floor or ceiling.
+    Walls(Bounds),
+    /// A line from one cell to another, including both.
+    Line(WorldPos, WorldPos),
+    Ellipsoid { bounds: Bounds, hollow: bool },
+    /// A cylinder running along `axis`, like a tower along `y`. A hollow cylinder is a tube,
+    /// open at both ends.
+    Cylinder { bounds: Bounds, axis: Axis, hollow: bool },
+    /// The top half of an ellipsoid, standing on the bottom of the box. A hollow dome has no
+    /// floor.
+    Dome { bounds: Bounds, hollow: bool },
+    /// A curved band that rises from the bottom of the box to its top and back down,
+    /// running through the box along `axis`, so `Axis::Z` makes an arch to walk through
+    /// north to south. Arches can't run along `y`, and `Axis::Y` is treated as `Axis::Z`.
+    Arch { bounds: Bounds, axis: Axis },
+}
+
+impl Shape {
+    /// A solid sphere centred on `center`.
+    pub fn sphere(center: impl Into<WorldPos>, radius: u32) -> Self {
+        let (center, radius) = (center.into(), radius as i32);
+        Shape::Ellipsoid {
+            bounds: Bounds::new(
+                center.offset(-radius, -radius, -radius),
+                center.offset(radius, radius, radius),
+            ),
+            hollow: false,
+        }
+    }
+
+    /// The smallest box containing the whole shape.
+    pub fn bounds(&self) -> Bounds {
+        match *self {
+            Shape::Box(bounds)
+            | Shape::HollowBox(bounds)
+            | Shape::Walls(bounds)
+            | Shape::Ellipsoid { bounds, .. }
+            | Shape::Cylinder { bounds, .. }
+            | Shape::Dome { bounds, .. }
+            | Shape::Arch { bounds, .. } => bounds,
+            Shape::Line(a, b) => Bounds::new(a, b),
+        }
+    }
+
+    pub fn contains(&self, pos: impl Into<WorldPos>) -> bool {
+        let pos = pos.into();
+        self.solid_contains(pos)
+            && (self.surface().is_empty()
+                || self.surface().iter().any(|dir| {
+                    let (dx, dy, dz) = dir.offset();
+                    !self.solid_contains(pos.offset(dx, dy, dz))
+                }))
+    }
+
+    /// Every cell in the shape.
+    pub fn positions(&self) -> Box<dyn Iterator<Item = WorldPos> + '_> {
+        match *self {
+            Shape::Box(bounds) => Box::new(bounds.positions()),
+            Shape::Line(a, b) => Box::new(line(a, b)),
+            _ => Box::new(self.bounds().positions().filter(|pos| self.contains(*pos))),
+        }
+    }
+
+    /// Whether `pos` is within the shape, if it were solid.
+    fn solid_contains(&self, pos: WorldPos) -> bool {
+        let bounds = self.bounds();
+        if !bounds.contains(pos) {
+            return false;
+        }
+
+        match *self {
+            Shape::Box(_) | Shape::HollowBox(_) | Shape::Walls(_) => true,
+            Shape::Line(a, b) => line(a, b).any(|cell| cell == pos),
+            Shape::Ellipsoid { .. } => in_ellipse(bounds, pos, &[Axis::X, Axis::Y, Axis::Z], false),
+            Shape::Cylinder { axis, .. } => {
+                let across: Vec<Axis> = [Axis::X, Axis::Y, Axis::Z]
+                    .into_iter()
+                    .filter(|other| *other != axis)
+                    .collect();
+                in_ellipse(bounds, pos, &across, false)
+            }
+            Shape::Dome { .. } => in_ellipse(bounds, pos, &[Axis::X, Axis::Y, Axis::Z], true),
+            Shape::Arch { axis, .. } => in_ellipse(bounds, pos, &[arch_span(axis), Axis::Y], true),
+        }
+    }
+
+    /// The directions to look for an outside cell in, for a cell to be on the shape's
+    /// surface, or none if the shape is solid.
+    fn surface(&self) -> &'static [Direction] {
+        use Direction::*;
+
+        match *self {
+            Shape::HollowBox(_) | Shape::Ellipsoid { hollow: true, .. } => &Direction::ALL,
+            Shape::Walls(_) | Shape::Cylinder { axis: Axis::Y, hollow: true, .. } => &[North, East, South, West],
+            Shape::Cylinder { axis: Axis::X, hollow: true, .. } => &[Up, Down, North, South],
+            Shape::Cylinder { axis: Axis::Z, hollow: true, .. } => &[Up, Down, East, West],
+            Shape::Dome { hollow: true, .. } => &[Up, North, East, South, West],
+            Shape::Arch { axis, .. } => match arch_span(axis) {
+                Axis::X => &[Up, East, West],
+                _ => &[Up, North, South],
+            },
+            _ => &[],
+        }
+    }
+}
+
+/// The horizontal axis an arch running along `axis` spans.
+fn arch_span(axis: Axis) -> Axis {
+    match axis {
+        Axis::X => Axis::Z,
+        Axis::Y | Axis::Z => Axis::X,
+    }
+}
+
+fn coord(pos: WorldPos, axis: Axis) -> i32 {
+    match axis {
+        Axis::X => pos.x,
+        Axis::Y => pos.y,
+        Axis::Z => pos.z,
+    }
+}
+
+/// Whether the centre of `pos` is within the ellipse or ellipsoid fitted to `bounds` along
+/// `axes`. If `half`, it's the top half of one twice as tall, standing on the bottom of
+/// the box.
+fn in_ellipse(bounds: Bounds, pos: WorldPos, axes: &[Axis], half: bool) -> bool {
+    let distance: f64 = axes
+        .iter()
+        .map(|&axis| {
+            let (min, max) = (coord(bounds.min(), axis) as f64, coord(bounds.max(), axis) as f64);
+            let (center, radius) = if half && axis == Axis::Y {
+                (min - 0.5, max - min + 1.0)
+            } else {
+                ((min + max) / 2.0, (max - min + 1.0) / 2.0)
+            };
+            let offset = (coord(pos, axis) as f64 - center) / radius;
+            offset * offset
+        })
+        .sum();
+
+    distance <= 1.0
+}
+
+/// The cells along a line from `a` to `b`, one for each step along its longest axis.
+fn line(a: WorldPos, b: WorldPos) -> impl Iterator<Item = WorldPos> {
+    let (dx, dy, dz) = (b.x - a.x, b.y - a.y, b.z - a.z);
+    let steps = dx.abs().max(dy.abs()).max(dz.abs());
+    let along = move |d: i32, step: i32| (d as f64 * step as f64 / steps.max(1) as f64).round() as i32;
+
+    (0..=steps).map(move |step| a.offset(along(dx, step), along(dy, step), along(dz, step)))
+}
@@ -0,0 +1,158 @@
+use super::{Axis, Bounds, ChunkSize, Layer, Rotation, Tile, TileMap, WorldPos};
+use crate::objects::{ObjectId, ObjectPos};
+
+/// A change to where everything in a map is.
+#[derive(Debug, Clone, Copy)]
+enum Transform {
+    Rotate(Rotation),
+    Mirror(Axis),
+    Translate(i32, i32, i32),
+}
+
+impl Transform {
+    /// Where the cell at `pos` ends up.
+    fn cell(self, pos: WorldPos) -> WorldPos {
+        match self {
+            // A cell is the unit box from its position, so turning it around the origin
+            // moves that box's other corner onto the new position.
+            Transform::Rotate(rotation) => (0..rotation.quarter_turns())
+                .fold(pos, |pos, _| WorldPos::new(-pos.z - 1, pos.y, pos.x)),
+            Transform::Mirror(Axis::X) => WorldPos::new(-pos.x - 1, pos.y, pos.z),
+            Transform::Mirror(Axis::Y) => WorldPos::new(pos.x, -pos.y - 1, pos.z),
+            Transform::Mirror(Axis::Z) => WorldPos::new(pos.x, pos.y, -pos.z - 1),
+            Transform::Translate(dx, dy, dz) => pos.offset(dx, dy, dz),
+        }
+    }
+
+    fn point(self, pos: ObjectPos) -> ObjectPos {
+        match self {
+            Transform::Rotate(rotation) => (0..rotation.quarter_turns())
+                .fold(pos, |pos, _| ObjectPos::new(-pos.z, pos.y, pos.x)),
+            Transform::Mirror(Axis::X) => ObjectPos::new(-pos.x, pos.y, pos.z),
+            Transform::Mirror(Axis::Y) => ObjectPos::new(pos.x, -pos.y, pos.z),
+            Transform::Mirror(Axis::Z) => ObjectPos::new(pos.x, pos.y, -pos.z),
+            Transform::Translate(dx, dy, dz) => {
+                ObjectPos::new(pos.x + dx as f32, pos.y + dy as f32, pos.z + dz as f32)
+            }
+        }
+    }
+
+    fn bounds(self, bounds: Bounds) -> Bounds {
+        Bounds::new(self.cell(bounds.min()), self.cell(bounds.max()))
+    }
+
+    fn tile(self, tile: Tile) -> Tile {
+        match self {
+            Transform::Rotate(rotation) => tile.rotate_y(rotation),
+            Transform::Mirror(axis) => tile.mirror(axis),
+            Transform::Translate(..) => tile,
+        }
+    }
+
+    /// An object's new rotation, in degrees clockwise from north.
+    fn object_rotation(self, degrees: f32) -> f32 {
+        let degrees = match self {
+            Transform::Rotate(rotation) => degrees + rotation.quarter_turns() as f32 * 90.0,
+            Transform::Mirror(Axis::X) => -degrees,
+            Transform::Mirror(Axis::Z) => 180.0 - degrees,
+            Transform::Mirror(Axis::Y) | Transform::Translate(..) => degrees,
+        };
+        degrees.rem_euclid(360.0)
+    }
+}
+
+/// A copy of `layer` with each cell moved to where `move_cell` says, or left out if it
+/// gives `None`.
+fn rebuild(
+    layer: &Layer,
+    size: ChunkSize,
+    move_cell: impl Fn(WorldPos) -> Option<WorldPos>,
+    change_tile: impl Fn(Tile) -> Tile,
+) -> Layer {
+    let mut rebuilt = Layer::new(layer.name.clone(), size);
+    rebuilt.visible = layer.visible;
+    rebuilt.locked = layer.locked;
+
+    for (pos, tile) in layer.tiles() {
+        if let Some(pos) = move_cell(pos) {
+            rebuilt.set_tile_at(pos, Some(change_tile(*tile)));
+        }
+    }
+    for (pos, data) in layer.tile_data() {
+        if let Some(pos) = move_cell(pos) {
+            rebuilt.set_tile_data(pos, data.clone());
+        }
+    }
+
+    rebuilt
+}
+
+impl TileMap {
+    /// Turns the whole map around the `y` axis, which runs up through the north-west corner
+    /// of the cell at the origin. Tiles are turned to match, and so are regions and objects.
+    pub fn rotate_y(&mut self, rotation: Rotation) {
+        self.transform(Transform::Rotate(rotation));
+    }
+
+    /// Reflects the whole map in the plane perpendicular to `axis` through the origin's
+    /// lowest corner, so the cell at `x = 0` swaps with the one at `x = -1` and so on.
+    /// Tiles are mirrored to match, and so are regions and objects.
+    pub fn mirror(&mut self, axis: Axis) {
+        self.transform(Transform::Mirror(axis));
+    }
+
+    /// Moves everything in the map by the given number of cells.
+    pub fn translate(&mut self, dx: i32, dy: i32, dz: i32) {
+        self.transform(Transform::Translate(dx, dy, dz));
+    }
+
+    /// Removes everything outside of `bounds`, on every layer. Regions are cut down to the
+    /// parts of them inside, and are removed if there's nothing left.
+    pub fn crop(&mut self, bounds: impl Into<Bounds>) {
+        let bounds = bounds.into();
+        let size = self.chunk_size;
+        for layer in &mut self.layers {
+            *layer = rebuild(layer, size, |pos| bounds.contains(pos).then_some(pos), |tile| tile);
+        }
+
+        for region in &mut self.regions {
+            region.boxes = region
+                .boxes
+                .iter()
+                .filter_map(|other| other.intersection(bounds))
+                .collect();
+        }
+        self.regions.retain(|region| !region.boxes.is_empty());
+
+        let outside: Vec<ObjectId> = self
+            .objects
+            .iter()
+            .filter(|(_, object)| !bounds.contains(object.position.cell()))
+            .map(|(id, _)| id)
+            .collect();
+        for id in outside {
+            self.objects.remove(id);
+        }
+    }
+
+    fn transform(&mut self, transform: Transform) {
+        let size = self.chunk_size;
+        for layer in &mut self.layers {
+            *layer = rebuild(layer, size, |pos| Some(transform.cell(pos)), |tile| transform.tile(tile));
+        }
+
+        for region in &mut self.regions {
+            for other in &mut region.boxes {
+                *other = transform.bounds(*other);
+            }
+        }
+
+        let ids: Vec<ObjectId> = self.objects.iter().map(|(id, _)| id).collect();
+        for id in ids {
+            self.objects.update(id, |object| {
+                object.position = transform.point(object.position);
+                object.rotation = transform.object_rotation(object.rotation);
+            });
+        }
+    }
+}
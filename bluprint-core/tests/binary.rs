@@ -0,0 +1,95 @@
+use bluprint_core::format::{self, MapError, MapFormat};
+use bluprint_core::objects::{MapObject, ObjectKind, ObjectType};
+use bluprint_core::regions::Region;
+use bluprint_core::tiles::{face, Color, Facing, Tile, TileData};
+use std::path::PathBuf;
+
+fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("bluprint-{}-{}", std::process::id(), name))
+}
+
+#[test]
+fn binary_map_round_trips() {
+    let mut map = bluprint_core::example::tilemap();
+    map.metadata_mut().title = "Round Trip".to_owned();
+    map.metadata_mut().tags.insert("test".to_owned());
+    map.metadata_mut().touch();
+    map.set_tile_range((-40, -70, -3)..=(20, 5, 2), Some(Tile::new(0)));
+    map.set_tile_range((-10, -10, -1)..=(-5, 0, 1), None);
+    map.set_tile_range((0, 0, 0)..=(3, 0, 3), Some(Tile::new(0).with_facing(face::EAST_SOUTH)));
+    map.set_tile_at((-1, -1, -1), None);
+    map.set_tile_data((2, 0, 2), TileData::default().with_tint(Color::rgba(255, 0, 0, 128)).with_tag("trap"));
+    map.set_tile_data((-30, 0, 0), TileData::default().with_note("Hidden lever"));
+
+    let props = map.add_layer("Props");
+    map.set_active_layer(props);
+    map.set_tile_range((5, 1, 5)..=(6, 2, 20), Some(Tile::new(0)));
+    map.active_layer_mut().visible = false;
+
+    map.object_types_mut().add_type(ObjectType::new(3, "Torch", ObjectKind::Light));
+    map.objects_mut().add(MapObject::new(3, (2.5, 1.0, -7.25)).with_rotation(90.0));
+    map.objects_mut().add(MapObject::new(3, (0.0, 0.0, 0.0)).with_scale(2.0).with_name("Brazier"));
+
+    map.add_region(
+        Region::new("1. Entrance")
+            .with_box((0, 0, 0)..=(9, 3, 5))
+            .with_box((-4, 0, 2)..=(-1, 3, 3))
+            .with_description("A draughty hall.")
+            .with_field("encounter", "2 goblins"),
+    );
+
+    let path = temp_path("round-trip.blub");
+    format::save_map(&map, &path).expect("map should save");
+    let reloaded = format::load_map(&path);
+    std::fs::remove_file(&path).ok();
+
+    assert_eq!(map, reloaded.expect("saved map should load"));
+}
+
+#[test]
+fn binary_map_is_smaller_than_json() {
+    let map = bluprint_core::example::tilemap();
+
+    let json = temp_path("size.blu");
+    let binary = temp_path("size.blub");
+    format::save_map_as(&map, &json, MapFormat::Json).unwrap();
+    format::save_map_as(&map, &binary, MapFormat::Binary).unwrap();
+    let json_len = std::fs::metadata(&json).unwrap().len();
+    let binary_len = std::fs::metadata(&binary).unwrap().len();
+    std::fs::remove_file(&json).ok();
+    std::fs::remove_file(&binary).ok();
+
+    assert!(binary_len * 10 < json_len, "{} bytes vs {} bytes of JSON", binary_len, json_len);
+}
+
+#[test]
+fn truncated_header_is_rejected() {
+    let path = temp_path("truncated.blub");
+    let mut bytes = format::binary::MAGIC.to_vec();
+    bytes.extend_from_slice(&u32::MAX.to_le_bytes());
+    bytes.extend_from_slice(b"{}");
+    std::fs::write(&path, bytes).unwrap();
+    let result = format::load_map(&path);
+    std::fs::remove_file(&path).ok();
+
+    assert!(matches!(result, Err(MapError::Corrupt(_))), "{:?}", result);
+}
+
+#[test]
+fn binary_map_with_every_facing_round_trips() {
+    let mut map = bluprint_core::example::tilemap();
+    for x in 0..32 {
+        for z in 0..32 {
+            let facing = Facing::all().nth(((x + z) % 24) as usize).unwrap();
+            map.set_tile_at((x, (x * z) % 8, z), Some(Tile::new(0).with_facing(facing)));
+        }
+    }
+    map.set_tile_range((-16, -2, -16)..=(15, -1, 15), Some(Tile::new(0)));
+
+    let path = temp_path("facings.blub");
+    format::save_map_as(&map, &path, MapFormat::Binary).unwrap();
+    let loaded = format::load_map(&path);
+    std::fs::remove_file(&path).ok();
+
+    assert_eq!(loaded.expect("saved map should load"), map);
+}
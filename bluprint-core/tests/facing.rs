@@ -0,0 +1,57 @@
+use bluprint_core::tiles::{face, Axis, Direction, Facing, InvalidFacing, Rotation};
+use std::collections::HashSet;
+
+#[test]
+fn facing_needs_perpendicular_directions() {
+    assert_eq!(Facing::new(Direction::Up, Direction::East), Ok(face::UP_EAST));
+    for dir in Direction::ALL {
+        assert_eq!(Facing::new(dir, dir), Err(InvalidFacing(dir, dir)));
+        assert_eq!(Facing::new(dir, dir.opposite()), Err(InvalidFacing(dir, dir.opposite())));
+    }
+    assert!(serde_json::from_str::<Facing>(r#"["North","South"]"#).is_err());
+}
+
+#[test]
+fn there_are_24_distinct_facings() {
+    let all: Vec<Facing> = Facing::all().collect();
+    assert_eq!(all.len(), 24);
+    assert_eq!(all.iter().collect::<HashSet<_>>().len(), 24);
+
+    for (index, facing) in all.into_iter().enumerate() {
+        assert_eq!(facing.index() as usize, index);
+        assert_eq!(Facing::from_index(index as u8), Some(facing));
+    }
+    assert_eq!(Facing::from_index(24), None);
+}
+
+#[test]
+fn facing_rotates_around_y() {
+    assert_eq!(face::UP_NORTH.rotate_y(Rotation::Deg90), face::UP_EAST);
+    assert_eq!(face::UP_NORTH.rotate_y(Rotation::Deg180), face::UP_SOUTH);
+    assert_eq!(face::UP_NORTH.rotate_y(Rotation::Deg270), face::UP_WEST);
+    assert_eq!(face::EAST_SOUTH.rotate_y(Rotation::Deg90), face::SOUTH_WEST);
+    assert_eq!(face::DOWN_WEST.rotate_y(Rotation::Deg90), face::DOWN_NORTH);
+
+    for facing in Facing::all() {
+        let turned = Rotation::ALL.map(|rotation| facing.rotate_y(rotation));
+        // Every facing has a direction that isn't along `y`, so each turn is different.
+        assert_eq!(turned.iter().collect::<HashSet<_>>().len(), 4);
+        assert_eq!(turned[0], facing);
+        assert_eq!(turned[1].rotate_y(Rotation::Deg270), facing);
+        assert_eq!(turned[1].rotate_y(Rotation::Deg90), turned[2]);
+    }
+}
+
+#[test]
+fn facing_mirrors_along_the_axis() {
+    assert_eq!(face::UP_EAST.mirror(Axis::X), face::UP_WEST);
+    assert_eq!(face::UP_EAST.mirror(Axis::Z), face::UP_EAST);
+    assert_eq!(face::NORTH_UP.mirror(Axis::Y), face::NORTH_DOWN);
+    assert_eq!(face::NORTH_UP.mirror(Axis::Z), face::SOUTH_UP);
+
+    for facing in Facing::all() {
+        for axis in [Axis::X, Axis::Y, Axis::Z] {
+            assert_eq!(facing.mirror(axis).mirror(axis), facing);
+        }
+    }
+}
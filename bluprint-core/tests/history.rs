@@ -0,0 +1,267 @@
+use bluprint_core::example;
+use bluprint_core::history::{Command, History};
+use bluprint_core::prefab::{PasteMode, PasteOptions};
+use bluprint_core::tiles::{face, Axis, Bounds, FloodFill, Rotation, Tile, TileData, TileMap, WorldPos};
+use std::collections::HashSet;
+
+#[test]
+fn undoing_a_new_tile_removes_its_chunk() {
+    let original = example::tilemap();
+    let mut map = original.clone();
+    let mut history = History::new();
+
+    history.apply(&mut map, Command::SetTile { pos: WorldPos::new(100, 0, 0), tile: Some(Tile::new(0)) });
+    assert_eq!(map.chunk_count(), original.chunk_count() + 1);
+
+    assert!(history.undo(&mut map));
+    assert_eq!(map, original);
+    assert_eq!(map.chunk_count(), original.chunk_count());
+}
+
+#[test]
+fn grouped_stroke_is_undone_and_redone_together() {
+    let original = example::tilemap();
+    let mut map = original.clone();
+    let mut history = History::new();
+
+    history.begin_group();
+    for x in 0..20 {
+        let pos = WorldPos::new(x, 5, 3);
+        history.apply(&mut map, Command::SetTile { pos, tile: Some(Tile::new(0).with_facing(face::UP_EAST)) });
+        history.apply(&mut map, Command::SetTileData { pos, data: TileData::default().with_tag("stroke") });
+    }
+    history.end_group();
+    let painted = map.clone();
+    assert_eq!(painted.occupied_count(), original.occupied_count() + 20);
+
+    assert!(history.undo(&mut map));
+    assert_eq!(map, original);
+    assert!(!history.can_undo());
+
+    assert!(history.redo(&mut map));
+    assert_eq!(map, painted);
+    assert!(!history.can_redo());
+}
+
+#[test]
+fn pasted_prefab_is_undone_and_redone() {
+    let original = example::tilemap();
+    let mut map = original.clone();
+    let mut history = History::new();
+
+    let prefab = map.copy_prefab((0, 0, 0)..=(3, 3, 3));
+    let options = PasteOptions::at((30, 0, 30))
+        .with_rotation(Rotation::Deg270)
+        .with_mirror(Axis::Z)
+        .with_mode(PasteMode::Overwrite);
+    history.apply(&mut map, Command::PastePrefab { prefab: prefab.clone(), options });
+    let pasted = map.clone();
+
+    let mut expected = original.clone();
+    expected.paste_prefab(&prefab, &options);
+    assert_eq!(pasted, expected);
+
+    assert!(history.undo(&mut map));
+    assert_eq!(map, original);
+    assert_eq!(map.chunk_count(), original.chunk_count());
+
+    assert!(history.redo(&mut map));
+    assert_eq!(map, pasted);
+}
+
+#[test]
+fn flood_fill_too_large_records_nothing() {
+    let mut map = example::tilemap();
+    let mut history = History::new();
+
+    let result = history.flood_fill(&mut map, (5, 0, 5), None, FloodFill::level().with_max_cells(10));
+    assert!(result.is_err());
+    assert!(!history.can_undo());
+
+    let original = map.clone();
+    assert_eq!(history.flood_fill(&mut map, (5, 0, 5), None, FloodFill::level()), Ok(16 * 16));
+    assert!(history.undo(&mut map));
+    assert_eq!(map, original);
+}
+
+#[test]
+fn step_over_the_memory_limit_is_forgotten_with_everything_before_it() {
+    let mut map = example::tilemap();
+    let mut history = History::with_memory_limit(4096);
+
+    history.apply(&mut map, Command::SetTile { pos: WorldPos::new(0, 9, 0), tile: Some(Tile::new(0)) });
+    assert!(history.can_undo());
+
+    let bounds = Bounds::new(WorldPos::new(0, 10, 0), WorldPos::new(15, 10, 15));
+    history.apply(&mut map, Command::SetTileRange { bounds, tile: Some(Tile::new(0)) });
+    assert!(!history.can_undo());
+    assert_eq!(history.memory_used(), 0);
+    assert_eq!(map.get_tile_at((15, 10, 15)).copied().flatten(), Some(Tile::new(0)));
+}
+
+#[test]
+fn oldest_steps_are_forgotten_to_stay_within_the_memory_limit() {
+    let mut map = example::tilemap();
+    let mut history = History::with_memory_limit(4096);
+
+    for x in 0..1000 {
+        history.apply(&mut map, Command::SetTile { pos: WorldPos::new(x, 9, 0), tile: Some(Tile::new(0)) });
+        assert!(history.memory_used() <= 4096);
+    }
+    let mut undone = 0;
+    while history.undo(&mut map) {
+        undone += 1;
+    }
+    assert!(undone > 0 && undone < 1000);
+    assert_eq!(map.get_tile_at((999, 9, 0)).copied().flatten(), None);
+    assert_eq!(map.get_tile_at((0, 9, 0)).copied().flatten(), Some(Tile::new(0)));
+}
+
+#[test]
+fn changed_chunks_are_reported() {
+    let mut map = example::tilemap();
+    let mut history = History::new();
+    let layer = map.active_layer_index();
+    let size = map.chunk_size();
+
+    let pos = WorldPos::new(100, 0, 0);
+    history.apply(&mut map, Command::SetTile { pos, tile: Some(Tile::new(0)) });
+    history.apply(&mut map, Command::SetTile { pos, tile: Some(Tile::new(0)) });
+    let changes = history.take_changes();
+    assert_eq!(changes.chunks, HashSet::from([(layer, pos.chunk(size))]));
+    assert!(history.take_changes().chunks.is_empty());
+
+    let data_pos = WorldPos::new(-40, 0, 0);
+    history.apply(&mut map, Command::SetTileData { pos: data_pos, data: TileData::default().with_tag("door") });
+    assert_eq!(history.take_changes().chunks, HashSet::from([(layer, data_pos.chunk(size))]));
+
+    assert!(history.undo(&mut map));
+    assert!(history.undo(&mut map));
+    assert_eq!(
+        history.take_changes().chunks,
+        HashSet::from([(layer, pos.chunk(size)), (layer, data_pos.chunk(size))])
+    );
+}
+
+/// The example map, with a second layer on top.
+fn layered_map() -> TileMap {
+    let mut map = example::tilemap();
+    let props = map.add_layer("Props");
+    map.set_active_layer(props);
+    map.set_tile_range((4, 4, 4)..=(6, 4, 6), Some(Tile::new(0).with_facing(face::UP_EAST)));
+    map.set_tile_at((2, 2, 2), None);
+    map.set_tile_data((5, 4, 5), TileData::default().with_tag("chest"));
+    map.set_active_layer(0);
+    map
+}
+
+#[test]
+fn layer_commands_are_undone_and_redone() {
+    let original = layered_map();
+    let mut map = original.clone();
+    let mut history = History::new();
+    let layers = map.layers().len();
+
+    let commands = [
+        Command::AddLayer { name: "Lights".to_string() },
+        Command::MoveLayer { from: layers, to: 0 },
+        Command::MergeLayers { from: 1, into: 0 },
+        Command::RemoveLayer { index: 0 },
+    ];
+    let mut states = vec![map.clone()];
+    for command in commands {
+        history.apply(&mut map, command);
+        states.push(map.clone());
+    }
+    assert_eq!(map.layers().len(), layers - 1);
+
+    for state in states.iter().rev().skip(1) {
+        assert!(history.undo(&mut map));
+        assert_eq!(&map, state);
+    }
+    assert_eq!(map, original);
+    for state in &states[1..] {
+        assert!(history.redo(&mut map));
+        assert_eq!(&map, state);
+    }
+}
+
+#[test]
+fn undoing_a_merge_keeps_the_layers_current_flags() {
+    let mut map = layered_map();
+    let mut history = History::new();
+
+    history.apply(&mut map, Command::MergeLayers { from: 1, into: 0 });
+    map.layer_mut(0).unwrap().visible = false;
+    assert!(history.undo(&mut map));
+
+    assert!(!map.layers()[0].visible);
+    assert_eq!(map.layers()[1], layered_map().layers()[1]);
+}
+
+#[test]
+fn transforms_are_undone() {
+    let original = layered_map();
+    let mut map = original.clone();
+    let mut history = History::new();
+
+    history.apply(&mut map, Command::RotateY(Rotation::Deg90));
+    history.apply(&mut map, Command::Mirror(Axis::X));
+    history.apply(&mut map, Command::Translate { dx: 3, dy: -2, dz: 40 });
+    assert!(history.take_changes().everything);
+    let transformed = map.clone();
+
+    while history.undo(&mut map) {}
+    assert_eq!(map, original);
+    while history.redo(&mut map) {}
+    assert_eq!(map, transformed);
+}
+
+#[test]
+fn tile_edits_on_a_removed_layer_are_undone_after_it_comes_back() {
+    let original = layered_map();
+    let mut map = original.clone();
+    let mut history = History::new();
+
+    map.set_active_layer(1);
+    history.apply(&mut map, Command::SetTile { pos: WorldPos::new(2, 2, 2), tile: Some(Tile::new(0)) });
+    history.apply(&mut map, Command::RemoveLayer { index: 1 });
+    history.apply(&mut map, Command::SetTile { pos: WorldPos::new(2, 9, 2), tile: Some(Tile::new(0)) });
+
+    while history.undo(&mut map) {}
+    assert_eq!(map.layers(), original.layers());
+    assert_eq!(map.active_layer_index(), 1);
+}
+
+#[test]
+fn locked_layers_are_left_alone() {
+    let mut map = layered_map();
+    let mut history = History::new();
+    let pos = WorldPos::new(2, 9, 2);
+
+    history.apply(&mut map, Command::SetTile { pos, tile: Some(Tile::new(0)) });
+    map.layer_mut(0).unwrap().locked = true;
+    let locked = map.clone();
+
+    history.apply(&mut map, Command::SetTile { pos, tile: None });
+    history.apply(&mut map, Command::RemoveLayer { index: 0 });
+    history.apply(&mut map, Command::MergeLayers { from: 1, into: 0 });
+    history.apply(&mut map, Command::RotateY(Rotation::Deg90));
+    assert_eq!(history.flood_fill(&mut map, (5, 0, 5), None, FloodFill::level()), Ok(0));
+    assert_eq!(map, locked);
+
+    assert!(history.undo_is_locked(&map));
+    assert!(!history.undo(&mut map));
+    assert_eq!(map, locked);
+
+    map.layer_mut(0).unwrap().locked = false;
+    assert!(history.undo(&mut map));
+    map.layer_mut(0).unwrap().locked = true;
+    assert!(history.redo_is_locked(&map));
+    assert!(!history.redo(&mut map));
+    assert_eq!(map.get_tile_at(pos).copied().flatten(), None);
+
+    // Moving a locked layer doesn't change it.
+    history.apply(&mut map, Command::MoveLayer { from: 0, to: 1 });
+    assert!(map.layers()[1].locked);
+}
@@ -0,0 +1,98 @@
+use bluprint_core::example;
+use bluprint_core::format;
+use bluprint_core::metadata::{GridUnit, MapMetadata};
+use bluprint_core::tiles::TileMap;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+fn seconds(seconds: u64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_secs(seconds)
+}
+
+fn save_and_load(map: &TileMap, name: &str) -> (serde_json::Value, TileMap) {
+    let path = std::env::temp_dir().join(format!("bluprint-{}-{}.blu", name, std::process::id()));
+    format::save_map(map, &path).expect("map should save");
+    let json = std::fs::read_to_string(&path).unwrap();
+    let reloaded = format::load_map(&path);
+    std::fs::remove_file(&path).ok();
+
+    (
+        serde_json::from_str(&json).unwrap(),
+        reloaded.expect("saved map should load"),
+    )
+}
+
+#[test]
+fn metadata_round_trips() {
+    let mut map = example::tilemap();
+    *map.metadata_mut() = MapMetadata {
+        title: "The Sunken Crypt".to_owned(),
+        author: "Jo".to_owned(),
+        description: "A two-level dungeon.".to_owned(),
+        grid_unit: GridUnit::new(1.5, "m"),
+        created: Some(seconds(1_767_225_600)),
+        modified: Some(seconds(1_767_312_000)),
+        tags: ["dungeon".to_owned(), "undead".to_owned()].into(),
+    };
+
+    let (json, reloaded) = save_and_load(&map, "metadata");
+
+    assert_eq!(json["metadata"]["created"], 1_767_225_600);
+    assert_eq!(json["metadata"]["modified"], 1_767_312_000);
+    assert_eq!(reloaded.metadata(), map.metadata());
+}
+
+#[test]
+fn times_before_the_epoch_round_trip() {
+    let mut map = example::tilemap();
+    map.metadata_mut().created = Some(UNIX_EPOCH - Duration::from_secs(86_400));
+    map.metadata_mut().modified = Some(UNIX_EPOCH);
+
+    let (json, reloaded) = save_and_load(&map, "metadata-before-epoch");
+
+    assert_eq!(json["metadata"]["created"], -86_400);
+    assert_eq!(json["metadata"]["modified"], 0);
+    assert_eq!(reloaded.metadata(), map.metadata());
+}
+
+#[test]
+fn empty_metadata_fields_are_left_out() {
+    let (json, reloaded) = save_and_load(&example::tilemap(), "metadata-empty");
+
+    let metadata = json["metadata"].as_object().unwrap();
+    let keys: Vec<&str> = metadata.keys().map(String::as_str).collect();
+    assert_eq!(keys, ["grid_unit"]);
+    assert_eq!(reloaded.metadata(), &MapMetadata::default());
+}
+
+#[test]
+fn missing_metadata_loads_as_default() {
+    let path = std::env::temp_dir().join(format!("bluprint-no-metadata-{}.blu", std::process::id()));
+    format::save_map(&example::tilemap(), &path).expect("map should save");
+    let mut json: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+    json.as_object_mut().unwrap().remove("metadata");
+    std::fs::write(&path, json.to_string()).unwrap();
+    let reloaded = format::load_map(&path);
+    std::fs::remove_file(&path).ok();
+
+    let reloaded = reloaded.expect("map without metadata should load");
+    assert_eq!(reloaded.metadata(), &MapMetadata::default());
+    assert_eq!(reloaded.metadata().grid_unit, GridUnit::new(5.0, "ft"));
+}
+
+#[test]
+fn touch_sets_created_once_and_modified_every_time() {
+    let mut metadata = MapMetadata::default();
+    metadata.touch();
+
+    let created = metadata.created.expect("touching should set the creation time");
+    assert_eq!(metadata.modified, Some(created));
+    assert_eq!(created.duration_since(UNIX_EPOCH).unwrap().subsec_nanos(), 0);
+    assert!(created <= SystemTime::now());
+
+    metadata.created = Some(seconds(1_000));
+    metadata.modified = Some(seconds(2_000));
+    metadata.touch();
+
+    assert_eq!(metadata.created, Some(seconds(1_000)));
+    assert!(metadata.modified.unwrap() >= created);
+}
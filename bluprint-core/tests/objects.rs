@@ -0,0 +1,102 @@
+use bluprint_core::format::{self, MapError};
+use bluprint_core::objects::{MapObject, ObjectId, ObjectPos, Objects};
+use bluprint_core::tiles::WorldPos;
+use std::collections::BTreeSet;
+
+/// Objects on both sides of bucket edges, including at negative positions.
+fn objects() -> (Objects, Vec<ObjectId>) {
+    let mut objects = Objects::new();
+    let ids = [
+        (0.5, 0.0, 0.5),
+        (7.9, 0.0, 7.9),
+        (8.0, 0.0, 8.0),
+        (-0.1, 0.0, -0.1),
+        (-8.5, 2.0, 3.0),
+        (100.0, 0.0, 100.0),
+    ]
+    .into_iter()
+    .map(|pos| objects.add(MapObject::new(0, pos)))
+    .collect();
+    (objects, ids)
+}
+
+fn ids<'a>(found: impl Iterator<Item = (ObjectId, &'a MapObject)>) -> BTreeSet<ObjectId> {
+    found.map(|(id, _)| id).collect()
+}
+
+#[test]
+fn objects_in_box() {
+    let (objects, ids) = objects();
+    assert_eq!(
+        self::ids(objects.in_box((0.0, 0.0, 0.0), (8.0, 0.0, 8.0))),
+        BTreeSet::from([ids[0], ids[1], ids[2]])
+    );
+    assert_eq!(
+        self::ids(objects.in_box((-9.0, 0.0, -1.0), (0.0, 2.0, 3.0))),
+        BTreeSet::from([ids[3], ids[4]])
+    );
+    assert_eq!(self::ids(objects.in_box((1.0, 0.0, 1.0), (7.0, 0.0, 7.0))), BTreeSet::new());
+}
+
+#[test]
+fn objects_within_radius() {
+    let (objects, ids) = objects();
+    assert_eq!(self::ids(objects.within((8.0, 0.0, 8.0), 0.2)), BTreeSet::from([ids[1], ids[2]]));
+    // The corners of the box around the circle are left out.
+    assert_eq!(self::ids(objects.within((0.0, 0.0, 0.0), 0.6)), BTreeSet::from([ids[3]]));
+    assert_eq!(self::ids(objects.within((0.0, 0.0, 0.0), 0.8)), BTreeSet::from([ids[0], ids[3]]));
+}
+
+#[test]
+fn objects_in_cell() {
+    let (objects, ids) = objects();
+    assert_eq!(self::ids(objects.in_cell((0, 0, 0))), BTreeSet::from([ids[0]]));
+    assert_eq!(self::ids(objects.in_cell((7, 0, 7))), BTreeSet::from([ids[1]]));
+    assert_eq!(self::ids(objects.in_cell((8, 0, 8))), BTreeSet::from([ids[2]]));
+    assert_eq!(self::ids(objects.in_cell((-1, 0, -1))), BTreeSet::from([ids[3]]));
+    assert_eq!(self::ids(objects.in_cell((-9, 2, 3))), BTreeSet::from([ids[4]]));
+    assert_eq!(self::ids(objects.in_cell((1, 0, 1))), BTreeSet::new());
+}
+
+#[test]
+fn moved_objects_are_found_in_their_new_place() {
+    let (mut objects, ids) = objects();
+    objects.update(ids[0], |object| object.position = ObjectPos::from(WorldPos::new(50, 0, 50)));
+    assert_eq!(self::ids(objects.in_cell((0, 0, 0))), BTreeSet::new());
+    assert_eq!(self::ids(objects.in_cell((50, 0, 50))), BTreeSet::from([ids[0]]));
+}
+
+#[test]
+fn largest_object_id_doesnt_overflow() {
+    let mut objects = Objects::new();
+    objects.insert(ObjectId(u64::MAX - 1), MapObject::new(0, (0.0, 0.0, 0.0)));
+    assert_eq!(objects.add(MapObject::new(0, (1.0, 0.0, 0.0))), ObjectId::MAX);
+}
+
+#[test]
+#[should_panic(expected = "no object ids left")]
+fn adding_after_the_largest_id_panics() {
+    let mut objects = Objects::new();
+    objects.insert(ObjectId::MAX, MapObject::new(0, (0.0, 0.0, 0.0)));
+    objects.add(MapObject::new(0, (1.0, 0.0, 0.0)));
+}
+
+#[test]
+fn largest_object_id_is_rejected() {
+    let path = std::env::temp_dir().join(format!("bluprint-max-object-{}.blu", std::process::id()));
+    std::fs::write(
+        &path,
+        format!(
+            r#"{{"version": {}, "tile_set": [], "layers": [],
+                "object_types": [{{"id": 0, "name": "Chest"}}],
+                "objects": [{{"id": {}, "type_id": 0, "position": {{"x": 0, "y": 0, "z": 0}}}}]}}"#,
+            format::CURRENT_VERSION,
+            u64::MAX
+        ),
+    )
+    .unwrap();
+    let result = format::load_map(&path);
+    std::fs::remove_file(&path).ok();
+
+    assert!(matches!(result, Err(MapError::Corrupt(_))), "{:?}", result);
+}
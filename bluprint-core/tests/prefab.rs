@@ -0,0 +1,247 @@
+use bluprint_core::format::MapError;
+use bluprint_core::prefab::{LibraryPrefab, PasteMode, PasteOptions, PrefabError, PrefabLibrary, PREFAB_EXTENSION};
+use bluprint_core::tiles::{face, Axis, Bounds, Rotation, Tile, TileData, TileMap, TileSet, TileType, WorldPos};
+
+use std::fs;
+
+fn tile_set() -> TileSet {
+    let mut tile_set = TileSet::new();
+    tile_set.add_tile(0, TileType::new(0, "Stone"));
+    tile_set.add_tile(1, TileType::new(1, "Stairs"));
+    tile_set
+}
+
+/// A map with a 3 x 1 x 2 box to copy at the origin: stone in its north-west corner, and
+/// stairs facing east in its south-east corner.
+fn source() -> TileMap {
+    let mut map = TileMap::new(tile_set());
+    map.set_tile_at((0, 0, 0), Some(Tile::new(0)));
+    map.set_tile_at((2, 0, 1), Some(Tile::new(1).with_facing(face::UP_EAST)));
+    map.set_tile_data((2, 0, 1), TileData::default().with_note("Down to the crypt"));
+    map
+}
+
+fn tiles(map: &TileMap) -> Vec<(WorldPos, Tile)> {
+    let mut tiles: Vec<(WorldPos, Tile)> = map.tiles().map(|(pos, tile)| (pos, *tile)).collect();
+    tiles.sort_by_key(|(pos, _)| *pos);
+    tiles
+}
+
+#[test]
+fn prefab_pastes_as_copied() {
+    let prefab = source().copy_prefab((0, 0, 0)..=(2, 0, 1));
+    assert_eq!(prefab.size(), (3, 1, 2));
+
+    let mut map = TileMap::new(tile_set());
+    let bounds = map.paste_prefab(&prefab, &PasteOptions::at((10, 0, 10)));
+    assert_eq!(bounds, Bounds::new((10, 0, 10), (12, 0, 11)));
+    assert_eq!(
+        tiles(&map),
+        vec![
+            (WorldPos::new(10, 0, 10), Tile::new(0)),
+            (WorldPos::new(12, 0, 11), Tile::new(1).with_facing(face::UP_EAST)),
+        ]
+    );
+    assert!(map.get_tile_data((12, 0, 11)).is_some());
+}
+
+#[test]
+fn prefab_pastes_rotated_and_mirrored() {
+    let prefab = source().copy_prefab((0, 0, 0)..=(2, 0, 1));
+
+    // Mirroring in x swaps the ends and turns the stairs to face west. A quarter turn
+    // clockwise then makes the box 2 wide and 3 deep, with west turned to north.
+    let mut map = TileMap::new(tile_set());
+    let options = PasteOptions::at((10, 0, 10))
+        .with_mirror(Axis::X)
+        .with_rotation(Rotation::Deg90);
+    let bounds = map.paste_prefab(&prefab, &options);
+    assert_eq!(bounds, Bounds::new((10, 0, 10), (11, 0, 12)));
+    assert_eq!(
+        tiles(&map),
+        vec![
+            (WorldPos::new(10, 0, 10), Tile::new(1).with_facing(face::UP_NORTH)),
+            (WorldPos::new(11, 0, 12), Tile::new(0).with_facing(face::UP_EAST)),
+        ]
+    );
+    assert_eq!(
+        map.get_tile_data((10, 0, 10)).and_then(|data| data.note.as_deref()),
+        Some("Down to the crypt")
+    );
+}
+
+#[test]
+fn prefab_paste_modes() {
+    let prefab = source().copy_prefab((0, 0, 0)..=(2, 0, 1));
+    let mut map = TileMap::new(tile_set());
+    map.set_tile_at((11, 0, 10), Some(Tile::new(1)));
+    let original = map.clone();
+
+    map.paste_prefab(&prefab, &PasteOptions::at((10, 0, 10)));
+    assert_eq!(map.get_tile_at((11, 0, 10)).copied().flatten(), Some(Tile::new(1)));
+
+    let mut map = original;
+    map.paste_prefab(&prefab, &PasteOptions::at((10, 0, 10)).with_mode(PasteMode::Overwrite));
+    assert_eq!(map.get_tile_at((11, 0, 10)).copied().flatten(), None);
+    assert_eq!(map.occupied_count(), 2);
+}
+
+#[test]
+fn prefab_paste_modes_and_data() {
+    let prefab = source().copy_prefab((0, 0, 0)..=(2, 0, 1));
+    let mut map = TileMap::new(tile_set());
+    // The stone is pasted over the trap, which the prefab has no data for, and nothing is
+    // pasted over the rune.
+    map.set_tile_data((10, 0, 10), TileData::default().with_tag("trap"));
+    map.set_tile_data((11, 0, 11), TileData::default().with_tag("rune"));
+    map.set_tile_data((13, 0, 10), TileData::default().with_tag("outside"));
+    let original = map.clone();
+
+    map.paste_prefab(&prefab, &PasteOptions::at((10, 0, 10)));
+    assert!(map.get_tile_data((10, 0, 10)).unwrap().has_tag("trap"));
+    assert!(map.get_tile_data((11, 0, 11)).unwrap().has_tag("rune"));
+    assert!(map.get_tile_data((12, 0, 11)).unwrap().note.is_some());
+
+    let mut map = original;
+    map.paste_prefab(&prefab, &PasteOptions::at((10, 0, 10)).with_mode(PasteMode::Overwrite));
+    assert_eq!(map.get_tile_data((10, 0, 10)), None);
+    assert_eq!(map.get_tile_data((11, 0, 11)), None);
+    assert!(map.get_tile_data((12, 0, 11)).unwrap().note.is_some());
+    assert!(map.get_tile_data((13, 0, 10)).unwrap().has_tag("outside"));
+}
+
+fn library(name: &str) -> PrefabLibrary {
+    let dir = std::env::temp_dir().join(format!("bluprint-library-{}-{}", std::process::id(), name));
+    let _ = fs::remove_dir_all(&dir);
+    PrefabLibrary::open(dir).unwrap()
+}
+
+fn stairs(name: &str) -> LibraryPrefab {
+    let map = source();
+    LibraryPrefab::new(name, map.copy_prefab((0, 0, 0)..=(2, 0, 1)), map.tile_set()).unwrap()
+}
+
+#[test]
+fn library_saves_and_loads() {
+    let library = library("saves");
+    library.save(&stairs("Stairs").with_tag("dungeon")).unwrap();
+    library.save(&stairs("Crypt")).unwrap();
+
+    let listing = library.search("dungeon").unwrap();
+    assert_eq!(listing.prefabs, vec![stairs("Stairs").with_tag("dungeon")]);
+    assert!(listing.failed.is_empty());
+    assert_eq!(library.load("Crypt").unwrap(), stairs("Crypt"));
+
+    library.remove("Crypt").unwrap();
+    assert_eq!(library.list().unwrap().prefabs.len(), 1);
+    fs::remove_dir_all(library.dir()).unwrap();
+}
+
+#[test]
+fn library_rejects_names_without_a_file_name() {
+    let library = library("names");
+    for name in ["", "  ", "/", "..", "_ _"] {
+        assert!(
+            matches!(library.save(&stairs(name)), Err(PrefabError::InvalidName(_))),
+            "{:?}",
+            name
+        );
+    }
+    assert!(library.path_of("a/b").is_ok());
+    fs::remove_dir_all(library.dir()).unwrap();
+}
+
+#[test]
+fn library_refuses_names_that_share_a_file() {
+    let library = library("collisions");
+    library.save(&stairs("a/b")).unwrap();
+    assert_eq!(library.path_of("a/b").unwrap(), library.path_of("a_b").unwrap());
+
+    assert!(matches!(
+        library.save(&stairs("a_b")),
+        Err(PrefabError::NameTaken { existing, .. }) if existing == "a/b"
+    ));
+    // Saving over a prefab with the same name is fine.
+    library.save(&stairs("a/b").with_tag("updated")).unwrap();
+    assert_eq!(library.load("a/b").unwrap(), stairs("a/b").with_tag("updated"));
+    fs::remove_dir_all(library.dir()).unwrap();
+}
+
+#[test]
+fn library_lists_files_it_cant_read() {
+    let library = library("bad-files");
+    library.save(&stairs("Stairs")).unwrap();
+    let corrupt = library.dir().join("Corrupt").with_extension(PREFAB_EXTENSION);
+    fs::write(&corrupt, "{ not json").unwrap();
+    let newer = library.dir().join("Newer").with_extension(PREFAB_EXTENSION);
+    fs::write(&newer, r#"{ "version": 1000 }"#).unwrap();
+
+    // Shrink the prefab's box so one of its cells is outside.
+    let outside = library.dir().join("Outside").with_extension(PREFAB_EXTENSION);
+    let mut doc = serde_json::to_value(stairs("Outside")).unwrap();
+    doc["version"] = 1.into();
+    doc["prefab"]["size"] = serde_json::json!([1, 1, 1]);
+    fs::write(&outside, doc.to_string()).unwrap();
+
+    let listing = library.list().unwrap();
+    assert_eq!(listing.prefabs, vec![stairs("Stairs")]);
+    let failed: Vec<_> = listing.failed.iter().map(|(path, _)| path.clone()).collect();
+    assert_eq!(failed, vec![corrupt, newer, outside]);
+    assert!(matches!(listing.failed[0].1, PrefabError::Map(MapError::Parse(_))));
+    assert!(matches!(listing.failed[1].1, PrefabError::UnsupportedVersion(1000)));
+    assert!(matches!(listing.failed[2].1, PrefabError::Map(MapError::Corrupt(_))));
+    fs::remove_dir_all(library.dir()).unwrap();
+}
+
+#[test]
+fn prefab_files_with_unsupported_versions_are_rejected() {
+    let library = library("versions");
+    // 2^32 + 1 would be read as version 1 if it were cut down to 32 bits.
+    for version in [0, 2, u32::MAX as u64 + 2] {
+        let path = library.dir().join("Stairs").with_extension(PREFAB_EXTENSION);
+        let mut doc = serde_json::to_value(stairs("Stairs")).unwrap();
+        doc["version"] = version.into();
+        fs::write(&path, doc.to_string()).unwrap();
+
+        let result = library.load("Stairs");
+        assert!(matches!(result, Err(PrefabError::UnsupportedVersion(v)) if v == version), "{:?}", result);
+    }
+    fs::remove_dir_all(library.dir()).unwrap();
+}
+
+#[test]
+fn prefab_files_with_unknown_tiles_are_rejected() {
+    let library = library("unknown-tiles");
+    let path = library.dir().join("Stairs").with_extension(PREFAB_EXTENSION);
+    let mut doc = serde_json::to_value(stairs("Stairs")).unwrap();
+    doc["version"] = 1.into();
+    doc["tile_types"].as_array_mut().unwrap().retain(|tile_type| tile_type["name"] != "Stairs");
+    fs::write(&path, doc.to_string()).unwrap();
+
+    let result = library.load("Stairs");
+    assert!(
+        matches!(result, Err(PrefabError::UnknownTile { tile_id: 1, pos }) if pos == WorldPos::new(2, 0, 1)),
+        "{:?}",
+        result
+    );
+    fs::remove_dir_all(library.dir()).unwrap();
+}
+
+#[test]
+fn prefab_with_tiles_missing_from_the_tile_set_is_rejected() {
+    let mut map = source();
+    map.set_tile_at((1, 0, 0), Some(Tile::new(7)));
+
+    let result = LibraryPrefab::new("Broken", map.copy_prefab((0, 0, 0)..=(2, 0, 1)), map.tile_set());
+    assert!(matches!(result, Err(PrefabError::UnknownTile { tile_id: 7, .. })), "{:?}", result);
+}
+
+#[test]
+fn tiles_without_a_type_are_left_out_of_pasted_prefabs() {
+    let mut prefab = stairs("Stairs");
+    prefab.tile_types.retain(|tile_type| tile_type.name != "Stairs");
+
+    let pasted = prefab.to_prefab(&mut tile_set());
+    let tiles: Vec<Tile> = pasted.cells().iter().filter_map(|cell| cell.tile).collect();
+    assert_eq!(tiles, vec![Tile::new(0)]);
+}
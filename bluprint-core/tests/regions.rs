@@ -0,0 +1,64 @@
+use bluprint_core::example;
+use bluprint_core::regions::Region;
+use bluprint_core::tiles::TileMap;
+
+/// A hall, a cellar under part of it, and a corridor whose two boxes overlap the hall's
+/// corner.
+fn regions_map() -> TileMap {
+    let mut map = example::tilemap();
+    map.add_region(Region::new("Hall").with_box((0, 0, 0)..=(7, 3, 7)));
+    map.add_region(Region::new("Cellar").with_box((2, -3, 2)..=(5, -1, 5)));
+    map.add_region(
+        Region::new("Corridor")
+            .with_box((6, 0, 6)..=(12, 0, 6))
+            .with_box((6, 0, 6)..=(6, 0, 12)),
+    );
+    map
+}
+
+fn names_at(map: &TileMap, pos: (i32, i32, i32)) -> Vec<&str> {
+    map.regions_at(pos).map(|(_, region)| region.name.as_str()).collect()
+}
+
+#[test]
+fn regions_at_finds_every_overlapping_region() {
+    let map = regions_map();
+
+    assert_eq!(names_at(&map, (3, 1, 3)), ["Hall"]);
+    assert_eq!(names_at(&map, (3, -2, 3)), ["Cellar"]);
+    assert_eq!(names_at(&map, (6, 0, 6)), ["Hall", "Corridor"]);
+    assert_eq!(names_at(&map, (10, 0, 6)), ["Corridor"]);
+    assert_eq!(names_at(&map, (6, 0, 10)), ["Corridor"]);
+    assert_eq!(names_at(&map, (10, 0, 10)), Vec::<&str>::new());
+
+    let indices: Vec<usize> = map.regions_at((7, 0, 6)).map(|(index, _)| index).collect();
+    assert_eq!(indices, [0, 2]);
+}
+
+#[test]
+fn regions_at_includes_edge_cells() {
+    let map = regions_map();
+
+    for corner in [(0, 0, 0), (7, 3, 7), (0, 3, 7), (7, 0, 0)] {
+        assert_eq!(names_at(&map, corner), ["Hall"], "{:?}", corner);
+    }
+    for outside in [(-1, 0, 0), (8, 3, 7), (0, 4, 0), (0, 0, -1)] {
+        assert!(names_at(&map, outside).is_empty(), "{:?}", outside);
+    }
+
+    assert_eq!(names_at(&map, (2, -1, 5)), ["Cellar"]);
+    assert_eq!(names_at(&map, (5, -3, 2)), ["Cellar"]);
+    assert_eq!(names_at(&map, (12, 0, 6)), ["Corridor"]);
+    assert_eq!(names_at(&map, (6, 0, 12)), ["Corridor"]);
+    assert!(names_at(&map, (13, 0, 6)).is_empty());
+    assert!(names_at(&map, (6, 1, 12)).is_empty());
+}
+
+#[test]
+fn regions_at_ignores_regions_without_boxes() {
+    let mut map = regions_map();
+    map.add_region(Region::new("Nowhere"));
+
+    assert_eq!(names_at(&map, (3, 1, 3)), ["Hall"]);
+    assert!(map.regions_at((100, 100, 100)).next().is_none());
+}
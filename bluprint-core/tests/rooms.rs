@@ -0,0 +1,172 @@
+use bluprint_core::rooms::{detect_rooms, RoomGraph, RoomOptions, DOOR_TAG};
+use bluprint_core::tiles::{Tile, TileData, TileMap, TileSet, TileType, WorldPos};
+
+const STONE: usize = 0;
+const DOOR: usize = 1;
+
+/// Two rooms three cells square on level 1, with a wall `thickness` cells thick between
+/// them. The wall starts at `x = 3`.
+fn two_rooms(thickness: i32) -> TileMap {
+    let mut tile_set = TileSet::new();
+    tile_set.add_tile(STONE, TileType::new(STONE, "Stone"));
+    tile_set.add_tile(
+        DOOR,
+        TileType {
+            solid: false,
+            ..TileType::new(DOOR, "Door")
+        },
+    );
+
+    let far = 6 + thickness;
+    let mut map = TileMap::new(tile_set);
+    map.set_tile_range((-1, 0, -1)..=(far, 0, 3), Some(Tile::new(STONE)));
+    map.set_tile_range((-1, 1, -1)..=(far, 1, 3), Some(Tile::new(STONE)));
+    map.set_tile_range((0, 1, 0)..=(2, 1, 2), None);
+    map.set_tile_range((3 + thickness, 1, 0)..=(5 + thickness, 1, 2), None);
+    map
+}
+
+#[test]
+fn rooms_either_side_of_a_door_are_joined() {
+    let mut map = two_rooms(1);
+    map.set_tile_at((3, 1, 1), Some(Tile::new(DOOR)));
+
+    let plan = detect_rooms(&map, &RoomOptions::new(1).with_door_type(DOOR));
+    assert_eq!(plan.rooms.len(), 2);
+    assert_eq!(plan.rooms[0].area(), 9);
+    assert_eq!(plan.rooms[1].area(), 9);
+    assert_eq!(plan.room_at((0, 1, 0)), Some(0));
+    assert_eq!(plan.room_at((4, 1, 0)), Some(1));
+    assert_eq!(plan.room_at((3, 1, 0)), None);
+
+    assert_eq!(plan.doorways.len(), 1);
+    assert_eq!(plan.doorways[0].pos, WorldPos::new(3, 1, 1));
+    assert_eq!(plan.doorways[0].rooms, [0, 1].into());
+    assert_eq!(plan.rooms[0].adjacent, [1].into());
+    assert_eq!(plan.rooms[1].adjacent, [0].into());
+}
+
+#[test]
+fn rooms_without_a_door_are_separate() {
+    let map = two_rooms(1);
+
+    let plan = detect_rooms(&map, &RoomOptions::new(1).with_door_type(DOOR));
+    assert_eq!(plan.rooms.len(), 2);
+    assert!(plan.doorways.is_empty());
+    assert!(plan.rooms[0].adjacent.is_empty());
+}
+
+#[test]
+fn thick_doorway_joins_the_rooms() {
+    let mut map = two_rooms(3);
+    map.set_tile_range((3, 1, 1)..=(5, 1, 1), Some(Tile::new(DOOR)));
+
+    let plan = detect_rooms(&map, &RoomOptions::new(1).with_door_type(DOOR));
+    assert_eq!(plan.rooms.len(), 2);
+    assert_eq!(plan.doorways.len(), 1);
+    assert_eq!(plan.doorways[0].cells.len(), 3);
+    assert_eq!(plan.doorways[0].rooms, [0, 1].into());
+    assert_eq!(plan.rooms[0].adjacent, [1].into());
+}
+
+#[test]
+fn tagged_wall_is_a_door() {
+    let mut map = two_rooms(1);
+    map.set_tile_data((3, 1, 2), TileData::default().with_tag(DOOR_TAG));
+
+    let plan = detect_rooms(&map, &RoomOptions::new(1));
+    assert_eq!(plan.doorways.len(), 1);
+    assert_eq!(plan.doorways[0].pos, WorldPos::new(3, 1, 2));
+    assert_eq!(plan.doorways[0].rooms, [0, 1].into());
+}
+
+/// A hall joined to a loop of three rooms, a dead end, and a room on its own.
+fn graph() -> RoomGraph {
+    let mut graph = RoomGraph::new();
+    for name in ["Hall", "North", "East", "South", "Closet", "Vault"] {
+        graph.add_room(name);
+    }
+    graph.connect(0, 1);
+    graph.connect(1, 2);
+    graph.connect(2, 3);
+    graph.connect(3, 1);
+    graph.connect(2, 4);
+    graph
+}
+
+#[test]
+fn graph_finds_dead_ends() {
+    assert_eq!(graph().dead_ends(), vec![0, 4]);
+}
+
+#[test]
+fn graph_dead_ends_ignore_connections_to_themselves() {
+    let mut graph = graph();
+    graph.connect(4, 4);
+    graph.connect(5, 5);
+    graph.connect(0, 0);
+    graph.connect(0, 0);
+
+    assert_eq!(graph.dead_ends(), vec![0, 4]);
+}
+
+#[test]
+fn graph_finds_loops() {
+    let loops = graph().loops();
+    assert_eq!(loops.len(), 1);
+
+    let mut rooms = loops[0].clone();
+    rooms.sort();
+    assert_eq!(rooms, vec![1, 2, 3]);
+}
+
+#[test]
+fn graph_finds_unreachable_rooms() {
+    let graph = graph();
+    assert_eq!(graph.unreachable_from(0), vec![5]);
+    assert_eq!(graph.distances_from(0), vec![Some(0), Some(1), Some(2), Some(2), Some(3), None]);
+    assert_eq!(graph.shortest_path(0, 4), Some(vec![0, 1, 2, 4]));
+    assert_eq!(graph.shortest_path(0, 5), None);
+    assert_eq!(graph.shortest_path(0, 6), None);
+}
+
+#[test]
+fn graph_draws_as_dot() {
+    let mut graph = RoomGraph::new();
+    graph.add_room("Entrance");
+    graph.add_room("The \"Pit\"");
+    graph.add_room("Vault");
+    graph.connect(0, 1);
+
+    assert_eq!(
+        graph.to_dot(Some(0)),
+        "graph rooms {\n    \
+             0 [label=\"Entrance\", peripheries=2, shape=box];\n    \
+             1 [label=\"The \\\"Pit\\\"\", shape=box];\n    \
+             2 [label=\"Vault\", style=dashed, fontcolor=gray, color=gray];\n    \
+             0 -- 1;\n\
+         }\n"
+    );
+}
+
+#[test]
+fn graph_draws_without_a_missing_entrance() {
+    let mut graph = RoomGraph::new();
+    graph.add_room("Entrance");
+    graph.add_room("Loop");
+    graph.connect(0, 1);
+    graph.connect(1, 1);
+
+    let dot = graph.to_dot(None);
+    assert_eq!(graph.to_dot(Some(2)), dot);
+    assert_eq!(graph.to_dot(Some(usize::MAX)), dot);
+    assert_eq!(
+        dot,
+        "graph rooms {\n    \
+             0 [label=\"Entrance\", shape=box];\n    \
+             1 [label=\"Loop\", shape=box];\n    \
+             0 -- 1;\n    \
+             1 -- 1;\n\
+         }\n"
+    );
+}
@@ -0,0 +1,147 @@
+use bluprint_core::example;
+use bluprint_core::format::{self, MapError, MapFormat};
+use bluprint_core::tiles::{face, ChunkSize, Color, Tile, TileData, TileMap};
+use std::path::PathBuf;
+
+fn maps_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../maps")
+}
+
+#[test]
+fn test_map_round_trips() {
+    let map = format::load_map(maps_dir().join("test.blu")).expect("maps/test.blu should load");
+
+    let out = std::env::temp_dir().join(format!("bluprint-round-trip-{}.blu", std::process::id()));
+    format::save_map(&map, &out).expect("map should save");
+    let reloaded = format::load_map(&out);
+    std::fs::remove_file(&out).ok();
+
+    assert_eq!(map, reloaded.expect("saved map should load"));
+}
+
+fn expected_map(chunk_size: ChunkSize) -> TileMap {
+    let mut map = example::tilemap();
+    map.set_chunk_size(chunk_size);
+    map
+}
+
+#[test]
+fn version_1_map_loads() {
+    let map = format::load_map(maps_dir().join("v1.blu")).expect("maps/v1.blu should load");
+    assert_eq!(map, example::tilemap());
+}
+
+#[test]
+fn version_2_map_loads() {
+    let map = format::load_map(maps_dir().join("v2.blu")).expect("maps/v2.blu should load");
+    assert_eq!(map, example::tilemap());
+}
+
+#[test]
+fn version_3_map_loads() {
+    let map = format::load_map(maps_dir().join("v3.blu")).expect("maps/v3.blu should load");
+    assert_eq!(map, expected_map(ChunkSize::new(4, 4, 4)));
+}
+
+#[test]
+fn version_4_map_loads() {
+    let map = format::load_map(maps_dir().join("v4.blu")).expect("maps/v4.blu should load");
+
+    let mut expected = expected_map(ChunkSize::new(4, 4, 4));
+    expected.set_tile_at((5, 1, 6), Some(Tile::new(0).with_facing(face::EAST_SOUTH)));
+    expected.set_tile_data(
+        (2, 0, 3),
+        TileData::default()
+            .with_tint(Color::rgb(255, 0, 0))
+            .with_tag("trap")
+            .with_note("Pressure plate"),
+    );
+    assert_eq!(map, expected);
+}
+
+#[test]
+fn version_too_large_is_rejected() {
+    let path = std::env::temp_dir().join(format!("bluprint-huge-version-{}.blu", std::process::id()));
+    std::fs::write(&path, r#"{"version": 4294967297, "tile_set": []}"#).unwrap();
+    let result = format::load_map(&path);
+    std::fs::remove_file(&path).ok();
+
+    assert!(matches!(result, Err(MapError::Migration(_))), "{:?}", result);
+}
+
+#[test]
+#[should_panic]
+fn chunk_size_too_large_panics() {
+    ChunkSize::new(300, 1, 300);
+}
+
+#[test]
+fn chunk_size_too_large_is_rejected() {
+    let path = std::env::temp_dir().join(format!("bluprint-huge-chunks-{}.blu", std::process::id()));
+    std::fs::write(
+        &path,
+        r#"{"version": 5, "tile_set": [], "chunk_size": {"x": 65536, "y": 65536, "z": 65536}, "layers": []}"#,
+    )
+    .unwrap();
+    let result = format::load_map(&path);
+    std::fs::remove_file(&path).ok();
+
+    assert!(matches!(result, Err(MapError::Corrupt(_))), "{:?}", result);
+}
+
+#[test]
+fn chunk_too_far_away_is_rejected() {
+    let path = std::env::temp_dir().join(format!("bluprint-far-chunk-{}.blu", std::process::id()));
+    let tiles = r#"[[[null, null], [null, null]], [[null, null], [null, null]]]"#;
+    std::fs::write(
+        &path,
+        format!(
+            r#"{{"version": 5, "tile_set": [], "chunk_size": {{"x": 2, "y": 2, "z": 2}},
+                "layers": [{{"name": "Layer 1", "chunks": [{{"position": {{"x": 2000000000, "y": 0, "z": 0}}, "tiles": {}}}]}}]}}"#,
+            tiles
+        ),
+    )
+    .unwrap();
+    let result = format::load_map(&path);
+    std::fs::remove_file(&path).ok();
+
+    assert!(matches!(result, Err(MapError::Corrupt(_))), "{:?}", result);
+}
+
+#[test]
+fn newer_version_is_refused() {
+    let path = std::env::temp_dir().join(format!("bluprint-newer-{}.blu", std::process::id()));
+    std::fs::write(
+        &path,
+        format!(r#"{{"version": {}, "tile_set": [], "layers": []}}"#, format::CURRENT_VERSION + 1),
+    )
+    .unwrap();
+    let result = format::load_map(&path);
+    std::fs::remove_file(&path).ok();
+
+    assert!(
+        matches!(result, Err(MapError::UnsupportedVersion(version)) if version == format::CURRENT_VERSION + 1),
+        "{:?}",
+        result
+    );
+}
+
+#[test]
+fn failed_save_keeps_the_old_file() {
+    let dir = std::env::temp_dir().join(format!("bluprint-failed-save-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("map.blub");
+    let map = example::tilemap();
+    format::save_map_as(&map, &path, MapFormat::Binary).expect("map should save");
+
+    // Tile ids in binary maps have to fit in a u32.
+    let mut broken = map.clone();
+    broken.set_tile_at((0, 0, 0), Some(Tile::new(u32::MAX as usize + 1)));
+    assert!(format::save_map_as(&broken, &path, MapFormat::Binary).is_err());
+
+    let reloaded = format::load_map(&path);
+    let files = std::fs::read_dir(&dir).unwrap().count();
+    std::fs::remove_dir_all(&dir).ok();
+    assert_eq!(reloaded.expect("old map should still load"), map);
+    assert_eq!(files, 1, "the temporary file should be removed");
+}
@@ -0,0 +1,358 @@
+use bluprint_core::example;
+use bluprint_core::tiles::{
+    face, Axis, Bounds, ChunkPos, FillTooLarge, FloodFill, Shape, Tile, TileData, TileMap, WorldPos,
+};
+
+#[test]
+fn reversed_range_fills_the_same_box() {
+    let mut forward = example::tilemap();
+    forward.set_tile_range((-2, 5, 1)..=(3, 6, 4), Some(Tile::new(0)));
+    let mut reversed = example::tilemap();
+    reversed.set_tile_range((3, 6, 4)..=(-2, 5, 1), Some(Tile::new(0)));
+
+    assert_eq!(forward, reversed);
+    assert_eq!(forward.tiles_in((3, 6, 4)..=(-2, 5, 1)).count(), 6 * 2 * 4);
+}
+
+#[test]
+fn reversed_range_is_the_same_bounds() {
+    let bounds = Bounds::from((3, 6, 4)..=(-2, 5, 1));
+    assert_eq!(bounds.min(), WorldPos::new(-2, 5, 1));
+    assert_eq!(bounds.max(), WorldPos::new(3, 6, 4));
+}
+
+#[test]
+fn map_iterators_cover_the_active_layer() {
+    let mut map = example::tilemap();
+    let props = map.add_layer("Props");
+    map.set_active_layer(props);
+    map.set_tile_range((20, 0, 0)..=(21, 0, 0), Some(Tile::new(0)));
+    map.set_tile_at((15, 0, 0), Some(Tile::new(0).with_facing(face::UP_EAST)));
+
+    let mut found: Vec<(WorldPos, Tile)> = map.tiles().map(|(pos, tile)| (pos, *tile)).collect();
+    found.sort_by_key(|(pos, _)| *pos);
+    assert_eq!(
+        found,
+        vec![
+            (WorldPos::new(15, 0, 0), Tile::new(0).with_facing(face::UP_EAST)),
+            (WorldPos::new(20, 0, 0), Tile::new(0)),
+            (WorldPos::new(21, 0, 0), Tile::new(0)),
+        ]
+    );
+    assert_eq!(map.tiles_in((0, 0, 0)..=(20, 0, 0)).count(), 2);
+    assert_eq!(map.chunk_tiles(ChunkPos::new(1, 0, 0)).count(), 2);
+    assert_eq!(map.chunk_tiles(ChunkPos::new(5, 5, 5)).count(), 0);
+
+    map.set_active_layer(0);
+    assert_eq!(map.tiles().count(), example::tilemap().occupied_count());
+    assert_eq!(map.chunk_tiles(ChunkPos::new(1, 0, 0)).count(), 0);
+}
+
+#[test]
+fn all_tiles_iterators_cover_every_layer() {
+    let mut map = example::tilemap();
+    let props = map.add_layer("Props");
+    map.set_active_layer(props);
+    map.set_tile_range((20, 0, 0)..=(21, 0, 0), Some(Tile::new(0)));
+    map.set_tile_at((0, 10, 0), None);
+
+    let base = example::tilemap().occupied_count();
+    assert_eq!(map.all_tiles().count(), base + 2);
+    assert_eq!(map.all_tiles().filter(|(layer, _, _)| *layer == props).count(), 2);
+
+    let mut found: Vec<(usize, WorldPos)> =
+        map.all_tiles_in((15, 0, 0)..=(20, 0, 0)).map(|(layer, pos, _)| (layer, pos)).collect();
+    found.sort();
+    assert_eq!(found, vec![(0, WorldPos::new(15, 0, 0)), (props, WorldPos::new(20, 0, 0))]);
+
+    assert_eq!(map.all_chunk_tiles(ChunkPos::new(1, 0, 0)).count(), 2);
+    assert_eq!(map.all_chunk_tiles(ChunkPos::new(0, 0, 0)).count(), base);
+    assert_eq!(map.all_chunk_tiles(ChunkPos::new(5, 5, 5)).count(), 0);
+}
+
+#[test]
+fn map_mutable_iterators_change_the_active_layer() {
+    let mut map = example::tilemap();
+    let props = map.add_layer("Props");
+    map.set_active_layer(props);
+    map.set_tile_range((0, 0, 0)..=(1, 0, 0), Some(Tile::new(0)));
+    map.set_tile_at((20, 0, 0), Some(Tile::new(0)));
+
+    let mut visited = 0;
+    map.for_each_tile_mut(|_, _| visited += 1);
+    assert_eq!(visited, 3);
+
+    map.for_each_tile_in_mut((0, 0, 0)..=(15, 0, 15), |_, tile| *tile = None);
+    assert_eq!(map.tiles().count(), 1);
+    map.for_each_chunk_tile_mut(ChunkPos::new(1, 0, 0), |pos, tile| {
+        assert_eq!(pos, WorldPos::new(20, 0, 0));
+        *tile = Some(Tile::new(0).with_facing(face::EAST_SOUTH));
+    });
+    assert_eq!(map.get_tile_at((20, 0, 0)), Some(&Some(Tile::new(0).with_facing(face::EAST_SOUTH))));
+
+    // The layer below is left alone.
+    assert_eq!(map.layer(0).unwrap(), example::tilemap().active_layer());
+}
+
+#[test]
+fn for_all_tiles_mut_changes_every_layer() {
+    let mut map = example::tilemap();
+    let props = map.add_layer("Props");
+    map.set_active_layer(props);
+    map.set_tile_at((20, 0, 0), Some(Tile::new(0)));
+
+    let mut visited = 0;
+    map.for_all_tiles_mut(|layer, _, tile| {
+        visited += 1;
+        if layer == props {
+            *tile = None;
+        }
+    });
+    assert_eq!(visited, example::tilemap().occupied_count() + 1);
+    assert_eq!(map.layer(props).unwrap().occupied_count(), 0);
+
+    map.for_all_tiles_in_mut((0, 0, 0)..=(15, 0, 15), |_, _, tile| *tile = None);
+    assert_eq!(map.all_tiles_in((0, 0, 0)..=(15, 0, 15)).count(), 0);
+
+    map.for_all_chunk_tiles_mut(ChunkPos::new(0, 0, 0), |_, pos, tile| {
+        *tile = Some(Tile::new(0).with_facing(face::EAST_SOUTH));
+        assert!(pos.y > 0);
+    });
+    assert!(map.all_tiles().all(|(_, _, tile)| tile.facing == face::EAST_SOUTH));
+}
+
+/// Fills `shape` into an empty map, checking that every tile is in it, and returns how many
+/// cells it filled.
+fn fill_count(shape: Shape) -> usize {
+    let mut map = TileMap::new(example::tileset());
+    map.fill_shape(&shape, Some(Tile::new(0)));
+    assert!(map.tiles().all(|(pos, _)| shape.contains(pos)), "{:?}", shape);
+    map.occupied_count()
+}
+
+/// Checks that `shape` is the same when mirrored across the middle of its box along each
+/// of `axes`.
+fn assert_symmetric(shape: Shape, axes: &[Axis]) {
+    let (min, max) = (shape.bounds().min(), shape.bounds().max());
+    for pos in shape.positions() {
+        for axis in axes {
+            let mirrored = match axis {
+                Axis::X => WorldPos::new(min.x + max.x - pos.x, pos.y, pos.z),
+                Axis::Y => WorldPos::new(pos.x, min.y + max.y - pos.y, pos.z),
+                Axis::Z => WorldPos::new(pos.x, pos.y, min.z + max.z - pos.z),
+            };
+            assert!(shape.contains(mirrored), "{:?} has {:?} but not {:?}", shape, pos, mirrored);
+        }
+    }
+}
+
+#[test]
+fn shapes_fill_the_right_cells() {
+    let bounds = Bounds::new((0, 0, 0), (4, 3, 5));
+
+    assert_eq!(fill_count(Shape::Box(bounds)), 5 * 4 * 6);
+    // Everything but the 3 x 2 x 4 inside.
+    assert_eq!(fill_count(Shape::HollowBox(bounds)), 5 * 4 * 6 - 3 * 2 * 4);
+    // Each of the 4 levels is a ring around a 3 x 4 hole.
+    assert_eq!(fill_count(Shape::Walls(bounds)), 4 * (5 * 6 - 3 * 4));
+    assert_eq!(fill_count(Shape::Line(WorldPos::new(0, 0, 0), WorldPos::new(4, 0, 2))), 5);
+}
+
+#[test]
+fn ellipsoid_fills_the_right_cells() {
+    let bounds = Bounds::new((0, 0, 0), (2, 2, 2));
+    let solid = Shape::Ellipsoid { bounds, hollow: false };
+    let hollow = Shape::Ellipsoid { bounds, hollow: true };
+
+    // Everything but the 8 corners.
+    assert_eq!(fill_count(solid), 27 - 8);
+    // Everything but the corners and the middle.
+    assert_eq!(fill_count(hollow), 27 - 8 - 1);
+    assert_eq!(Shape::sphere((1, 1, 1), 1), solid);
+
+    let stretched = Bounds::new((-3, 0, 2), (3, 4, 6));
+    for shape in [solid, hollow] {
+        assert_symmetric(shape, &[Axis::X, Axis::Y, Axis::Z]);
+    }
+    for hollow in [false, true] {
+        assert_symmetric(Shape::Ellipsoid { bounds: stretched, hollow }, &[Axis::X, Axis::Y, Axis::Z]);
+    }
+}
+
+#[test]
+fn cylinder_fills_the_right_cells() {
+    let bounds = Bounds::new((0, 0, 0), (4, 2, 4));
+    let solid = Shape::Cylinder { bounds, axis: Axis::Y, hollow: false };
+    let hollow = Shape::Cylinder { bounds, axis: Axis::Y, hollow: true };
+
+    // Each of the 3 levels is a 5 x 5 square without its corners.
+    assert_eq!(fill_count(solid), 3 * (25 - 4));
+    // ...and without the 3 x 3 middle either.
+    assert_eq!(fill_count(hollow), 3 * (25 - 4 - 9));
+
+    // Lying along x or z, the same cylinder has the same number of cells.
+    let along_x = Shape::Cylinder { bounds: Bounds::new((0, 0, 0), (2, 4, 4)), axis: Axis::X, hollow: true };
+    let along_z = Shape::Cylinder { bounds: Bounds::new((0, 0, 0), (4, 4, 2)), axis: Axis::Z, hollow: true };
+    assert_eq!(fill_count(along_x), fill_count(hollow));
+    assert_eq!(fill_count(along_z), fill_count(hollow));
+
+    for shape in [solid, hollow, along_x, along_z] {
+        assert_symmetric(shape, &[Axis::X, Axis::Y, Axis::Z]);
+    }
+}
+
+#[test]
+fn dome_fills_the_right_cells() {
+    let bounds = Bounds::new((0, 0, 0), (4, 2, 4));
+    let solid = Shape::Dome { bounds, hollow: false };
+    let hollow = Shape::Dome { bounds, hollow: true };
+
+    // From the bottom up, a 5 x 5 square without its corners, a 3 x 3 square with a cell
+    // added to the middle of each side, and a plus of 5.
+    assert_eq!(fill_count(solid), 21 + 13 + 5);
+    // The top level, and 8 and 12 cells of the others with an outside cell above or beside
+    // them.
+    assert_eq!(fill_count(hollow), 5 + 8 + 12);
+    // The dome has no floor, so the middle of its bottom level is open.
+    assert!(!hollow.contains((2, 0, 2)));
+    assert!(hollow.contains((2, 2, 2)));
+
+    for shape in [solid, hollow] {
+        assert_symmetric(shape, &[Axis::X, Axis::Z]);
+    }
+}
+
+#[test]
+fn arch_fills_the_right_cells() {
+    let bounds = Bounds::new((0, 0, 0), (4, 2, 3));
+    let arch = Shape::Arch { bounds, axis: Axis::Z };
+
+    // Each of the 4 slices is the 3 cells of the top level, and both ends of the others.
+    assert_eq!(fill_count(arch), 4 * (3 + 2 + 2));
+    // The way through is open.
+    assert!((0..=1).all(|y| (1..=3).all(|x| !arch.contains((x, y, 0)))));
+
+    let along_x = Shape::Arch { bounds: Bounds::new((0, 0, 0), (3, 2, 4)), axis: Axis::X };
+    assert_eq!(fill_count(along_x), fill_count(arch));
+    // `Axis::Y` is treated as `Axis::Z`.
+    assert_eq!(fill_count(Shape::Arch { bounds, axis: Axis::Y }), fill_count(arch));
+
+    for shape in [arch, along_x] {
+        assert_symmetric(shape, &[Axis::X, Axis::Z]);
+    }
+}
+
+#[test]
+fn hollow_box_is_open_inside() {
+    let mut map = TileMap::new(example::tileset());
+    map.fill_shape(&Shape::HollowBox(Bounds::new((0, 0, 0), (2, 2, 2))), Some(Tile::new(0)));
+
+    assert_eq!(map.occupied_count(), 26);
+    assert_eq!(map.get_tile_at((1, 1, 1)).copied().flatten(), None);
+}
+
+#[test]
+fn flood_fill_follows_the_seed_type() {
+    let mut map = example::tilemap();
+    assert_eq!(map.flood_fill((5, 0, 5), None, FloodFill::level()), Ok(16 * 16));
+    assert_eq!(map.occupied_count(), example::tilemap().occupied_count() - 16 * 16);
+
+    let mut map = example::tilemap();
+    let all = map.occupied_count();
+    assert_eq!(map.flood_fill((5, 0, 5), None, FloodFill::new()), Ok(all));
+    assert_eq!(map.occupied_count(), 0);
+}
+
+#[test]
+fn flood_fill_too_large_changes_nothing() {
+    let mut map = example::tilemap();
+    let result = map.flood_fill((5, 0, 5), None, FloodFill::level().with_max_cells(16 * 16 - 1));
+    assert_eq!(result, Err(FillTooLarge { max_cells: 16 * 16 - 1 }));
+    assert_eq!(map, example::tilemap());
+
+    // Empty space goes on forever.
+    let result = map.flood_fill((50, 50, 50), Some(Tile::new(0)), FloodFill::new().with_max_cells(1000));
+    assert_eq!(result, Err(FillTooLarge { max_cells: 1000 }));
+    assert_eq!(map, example::tilemap());
+
+    // Exactly the limit is fine.
+    let result = map.flood_fill((5, 0, 5), None, FloodFill::level().with_max_cells(16 * 16));
+    assert_eq!(result, Ok(16 * 16));
+}
+
+/// A map with four empty layers called "0" to "3", with layer 2 active.
+fn four_layers() -> TileMap {
+    let mut map = example::tilemap();
+    map.layer_mut(0).unwrap().name = "0".to_string();
+    for index in 1..4 {
+        assert_eq!(map.add_layer(index.to_string()), index);
+    }
+    map.set_active_layer(2);
+    map
+}
+
+fn layer_names(map: &TileMap) -> Vec<&str> {
+    map.layers().iter().map(|layer| layer.name.as_str()).collect()
+}
+
+#[test]
+fn removing_layers_keeps_the_active_layer_in_range() {
+    let mut map = four_layers();
+
+    assert_eq!(map.remove_layer(4), None);
+    assert_eq!(map.remove_layer(3).unwrap().name, "3");
+    assert_eq!(map.active_layer().name, "2");
+    assert_eq!(map.remove_layer(0).unwrap().name, "0");
+    assert_eq!(map.active_layer().name, "2");
+    assert_eq!(map.remove_layer(1).unwrap().name, "2");
+    assert_eq!(map.active_layer().name, "1");
+    assert_eq!(map.remove_layer(0), None);
+    assert_eq!(layer_names(&map), ["1"]);
+}
+
+#[test]
+fn moving_layers_keeps_the_active_layer_active() {
+    let mut map = four_layers();
+
+    map.move_layer(0, 3);
+    assert_eq!(layer_names(&map), ["1", "2", "3", "0"]);
+    assert_eq!(map.active_layer_index(), 1);
+    map.move_layer(1, 2);
+    assert_eq!(layer_names(&map), ["1", "3", "2", "0"]);
+    assert_eq!(map.active_layer_index(), 2);
+    map.move_layer(3, 0);
+    assert_eq!(layer_names(&map), ["0", "1", "3", "2"]);
+    assert_eq!(map.active_layer().name, "2");
+}
+
+#[test]
+fn merging_layers_copies_tiles_and_data_over() {
+    let mut map = four_layers();
+    let below = Some(Tile::new(0));
+    let above = Some(Tile::new(0).with_facing(face::UP_EAST));
+    map.layer_mut(1).unwrap().set_tile_range((0, 0, 0)..=(1, 0, 0), below);
+    map.layer_mut(3).unwrap().set_tile_at((1, 0, 0), above);
+    map.layer_mut(3).unwrap().set_tile_data((1, 0, 0), TileData::default().with_tag("rug"));
+
+    map.merge_layers(3, 1);
+    assert_eq!(layer_names(&map), ["0", "1", "2"]);
+    assert_eq!(map.active_layer().name, "2");
+    let merged = map.layer(1).unwrap();
+    assert_eq!(merged.get_tile_at((0, 0, 0)), Some(&below));
+    assert_eq!(merged.get_tile_at((1, 0, 0)), Some(&above));
+    assert!(merged.get_tile_data((1, 0, 0)).unwrap().has_tag("rug"));
+
+    map.merge_layers(2, 0);
+    assert_eq!(layer_names(&map), ["0", "1"]);
+    assert_eq!(map.active_layer().name, "0");
+}
+
+#[test]
+fn inserted_layer_goes_back_where_it_was() {
+    let original = four_layers();
+    let mut map = original.clone();
+
+    let layer = map.remove_layer(1).unwrap();
+    map.insert_layer(1, layer);
+    assert_eq!(map, original);
+}
@@ -0,0 +1,59 @@
+use bluprint_core::example;
+use bluprint_core::objects::{MapObject, ObjectKind, ObjectType};
+use bluprint_core::regions::Region;
+use bluprint_core::tiles::{face, Axis, Rotation, Tile, TileData, TileMap, WorldPos};
+
+/// The example map, with something of each kind a transform has to move: a turned tile,
+/// tile data, a second layer, a region and a turned object.
+fn map() -> TileMap {
+    let mut map = example::tilemap();
+    map.set_tile_at((5, 1, 6), Some(Tile::new(0).with_facing(face::EAST_SOUTH)));
+    map.set_tile_data((2, 0, 3), TileData::default().with_tag("trap").with_note("Pressure plate"));
+
+    let layer = map.add_layer("Props");
+    map.set_active_layer(layer);
+    map.set_tile_at((-3, 4, 7), Some(Tile::new(0).with_facing(face::UP_EAST)));
+    map.set_active_layer(0);
+
+    map.add_region(Region::new("Hall").with_box((0, 0, 0)..=(15, 3, 7)).with_box((4, 0, 8)..=(6, 0, 12)));
+    map.object_types_mut().add_type(ObjectType::new(0, "Chest", ObjectKind::Container));
+    map.objects_mut().add(MapObject::new(0, (2.5, 1.0, 3.25)).with_rotation(45.0));
+    map
+}
+
+#[test]
+fn four_quarter_turns_are_the_identity() {
+    let original = map();
+    let mut map = original.clone();
+
+    map.rotate_y(Rotation::Deg90);
+    assert_ne!(map, original);
+    assert_eq!(map.get_tile_at((-7, 1, 5)).copied().flatten(), Some(Tile::new(0).with_facing(face::SOUTH_WEST)));
+
+    for _ in 0..3 {
+        map.rotate_y(Rotation::Deg90);
+    }
+    assert_eq!(map, original);
+}
+
+#[test]
+fn mirroring_twice_is_the_identity() {
+    let original = map();
+    for axis in [Axis::X, Axis::Y, Axis::Z] {
+        let mut map = original.clone();
+        map.mirror(axis);
+        assert_ne!(map, original, "{:?}", axis);
+        map.mirror(axis);
+        assert_eq!(map, original, "{:?}", axis);
+    }
+}
+
+#[test]
+fn translating_back_is_the_identity() {
+    let original = map();
+    let mut map = original.clone();
+    map.translate(3, -2, 40);
+    assert_eq!(map.regions()[0].bounds().map(|bounds| bounds.min()), Some(WorldPos::new(3, -2, 40)));
+    map.translate(-3, 2, -40);
+    assert_eq!(map, original);
+}
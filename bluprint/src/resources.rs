@@ -1,2 +1,3 @@
+pub mod errors;
 pub mod map_file;
 pub mod tabs;
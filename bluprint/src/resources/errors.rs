@@ -0,0 +1,21 @@
+/// Errors waiting to be shown to the user.
+#[derive(Default)]
+pub struct Errors {
+    messages: Vec<String>,
+}
+
+impl Errors {
+    pub fn push(&mut self, message: impl Into<String>) {
+        self.messages.push(message.into());
+    }
+
+    pub fn current(&self) -> Option<&str> {
+        self.messages.first().map(String::as_str)
+    }
+
+    pub fn dismiss(&mut self) {
+        if !self.messages.is_empty() {
+            self.messages.remove(0);
+        }
+    }
+}
@@ -1,13 +1,167 @@
+use bluprint_core::format::{self, MapError};
+use bluprint_core::history::{Command, History};
+use bluprint_core::tiles::{ChunkPos, Direction, FillTooLarge, FloodFill, Tile, TileMap, WorldPos};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
 pub struct MapFile {
-    path: std::path::PathBuf,
+    id: usize,
+    path: PathBuf,
+    map: TileMap,
+    /// The format version the file on disk was written with.
+    version: u32,
+    /// Counts changes to the map that the renderer can't track chunk by chunk, so it knows
+    /// to redraw everything.
+    revision: u64,
+    /// The chunks, by layer index and position, whose meshes are out of date since the
+    /// renderer last took them.
+    changed_chunks: HashSet<(usize, ChunkPos)>,
+    history: History,
 }
 
 impl MapFile {
-    pub fn new(path: std::path::PathBuf) -> Self {
-        Self { path }
+    pub fn new(path: PathBuf, map: TileMap) -> Self {
+        Self {
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            path,
+            map,
+            version: format::CURRENT_VERSION,
+            revision: 0,
+            changed_chunks: HashSet::new(),
+            history: History::new(),
+        }
+    }
+
+    pub fn open(path: PathBuf) -> Result<Self, MapError> {
+        let (map, version) = format::load_map_with_version(&path)?;
+        Ok(Self {
+            version,
+            ..Self::new(path, map)
+        })
+    }
+
+    /// Uniquely identifies this open map, even if the same file is opened twice.
+    pub fn id(&self) -> usize {
+        self.id
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
     }
 
     pub fn file_name(&self) -> &str {
         self.path.file_name().unwrap().to_str().unwrap()
     }
+
+    /// The map's title, or its file name if it doesn't have one.
+    pub fn title(&self) -> &str {
+        match self.map.metadata().title.trim() {
+            "" => self.file_name(),
+            title => title,
+        }
+    }
+
+    pub fn map(&self) -> &TileMap {
+        &self.map
+    }
+
+    pub fn map_mut(&mut self) -> &mut TileMap {
+        &mut self.map
+    }
+
+    /// Changes whenever [`MapFile::mark_edited`] is called.
+    pub fn revision(&self) -> u64 {
+        self.revision
+    }
+
+    /// Records that the map was changed in a way that can move any tile, like rotating it
+    /// or reordering its layers, so every chunk is meshed again.
+    pub fn mark_edited(&mut self) {
+        self.revision += 1;
+    }
+
+    /// Takes the chunks whose meshes are out of date because of edits since the last call.
+    pub fn take_changed_chunks(&mut self) -> HashSet<(usize, ChunkPos)> {
+        std::mem::take(&mut self.changed_chunks)
+    }
+
+    /// Runs an edit to the map through the history, noting which chunks it changed. Faces
+    /// are culled against neighbouring chunks, so those are meshed again too.
+    fn edit<T>(&mut self, edit: impl FnOnce(&mut TileMap, &mut History) -> T) -> T {
+        let result = edit(&mut self.map, &mut self.history);
+
+        let changes = self.history.take_changes();
+        if changes.everything {
+            self.mark_edited();
+        }
+        for (index, cpos) in changes.chunks {
+            self.changed_chunks.insert((index, cpos));
+            for dir in Direction::ALL {
+                let (dx, dy, dz) = dir.offset();
+                let neighbour = ChunkPos::new(cpos.x + dx, cpos.y + dy, cpos.z + dz);
+                self.changed_chunks.insert((index, neighbour));
+            }
+        }
+
+        result
+    }
+
+    pub fn history(&self) -> &History {
+        &self.history
+    }
+
+    /// Edits the map in a way that can be undone.
+    pub fn apply(&mut self, command: Command) {
+        self.edit(|map, history| history.apply(map, command));
+    }
+
+    /// Flood fills the map in a way that can be undone. See [`History::flood_fill`].
+    pub fn flood_fill(&mut self, seed: WorldPos, tile: Option<Tile>, options: FloodFill) -> Result<usize, FillTooLarge> {
+        self.edit(|map, history| history.flood_fill(map, seed, tile, options))
+    }
+
+    pub fn undo(&mut self) {
+        self.edit(|map, history| history.undo(map));
+    }
+
+    pub fn redo(&mut self) {
+        self.edit(|map, history| history.redo(map));
+    }
+
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    /// Whether saving over the file will upgrade it to a newer format version.
+    pub fn is_outdated(&self) -> bool {
+        self.version < format::CURRENT_VERSION
+    }
+
+    pub fn save(&mut self) -> Result<(), MapError> {
+        let path = self.path.clone();
+        self.write(&path)
+    }
+
+    /// Saves the map to a new location, which becomes the tab's file from then on.
+    pub fn save_as(&mut self, path: PathBuf) -> Result<(), MapError> {
+        self.write(&path)?;
+        self.path = path;
+        Ok(())
+    }
+
+    /// Writes the map to `path` with its modification time set to now, keeping the old
+    /// times if it couldn't be saved.
+    fn write(&mut self, path: &Path) -> Result<(), MapError> {
+        let metadata = self.map.metadata().clone();
+        self.map.metadata_mut().touch();
+        if let Err(err) = format::save_map(&self.map, path) {
+            *self.map.metadata_mut() = metadata;
+            return Err(err);
+        }
+        self.version = format::CURRENT_VERSION;
+        Ok(())
+    }
 }
@@ -15,6 +15,14 @@ impl Tabs {
         }
     }
 
+    pub fn current_tab_mut(&mut self) -> Option<&mut MapFile> {
+        if let Some(tab) = self.current_tab {
+            Some(self.tabs.get_mut(tab).expect("Tab index out of bounds"))
+        } else {
+            None
+        }
+    }
+
     pub fn current_tab_idx(&self) -> Option<&usize> {
         self.current_tab.as_ref()
     }
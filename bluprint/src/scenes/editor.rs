@@ -1,23 +1,481 @@
-use crate::resources::{map_file::MapFile, tabs::Tabs};
+use crate::resources::{errors::Errors, map_file::MapFile, tabs::Tabs};
 use bevy::prelude::*;
 use bevy_egui::{egui, EguiContext};
+use bluprint_core::format;
+use bluprint_core::history::Command;
+use bluprint_core::prefab::{LibraryPrefab, PasteMode, PasteOptions, PrefabLibrary, Thumbnail};
+use bluprint_core::tiles::{Axis, Bounds, Rotation};
 use iyes_loopless::prelude::*;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 use crate::AppState;
 
 pub struct EditorPlugin;
 
-fn draw_ui(mut egui_context: ResMut<EguiContext>, mut tabs: ResMut<Tabs>) {
+fn save_tab(tab: &mut MapFile, errors: &mut Errors) {
+    if let Err(err) = tab.save() {
+        errors.push(format!("Couldn't save {}: {}", tab.path().display(), err));
+    }
+}
+
+/// Lists the current map's layers, topmost first, with controls for editing them.
+fn layers_panel(ctx: &egui::Context, tab: &mut MapFile) {
+    egui::SidePanel::left("layers").show(ctx, |ui| {
+        ui.heading("Layers");
+
+        let mut command = None;
+        let map = tab.map_mut();
+        let mut active = map.active_layer_index();
+        for index in (0..map.layers().len()).rev() {
+            let layer = map.layer_mut(index).unwrap();
+            ui.horizontal(|ui| {
+                ui.checkbox(&mut layer.visible, "")
+                    .on_hover_text("Visible");
+                ui.checkbox(&mut layer.locked, "")
+                    .on_hover_text("Locked");
+                ui.selectable_value(&mut active, index, &layer.name);
+            });
+        }
+        map.set_active_layer(active);
+
+        ui.separator();
+        ui.horizontal(|ui| {
+            // Buttons for commands that would change a locked layer are disabled.
+            let mut button = |ui: &mut egui::Ui, label: &str, enabled: bool, clicked: Command| {
+                let enabled = enabled && !clicked.is_locked(map);
+                if ui.add_enabled(enabled, egui::Button::new(label)).clicked() {
+                    command = Some(clicked);
+                }
+            };
+            let name = format!("Layer {}", map.layers().len() + 1);
+            button(ui, "Add", true, Command::AddLayer { name });
+            button(ui, "Remove", map.layers().len() > 1, Command::RemoveLayer { index: active });
+            button(
+                ui,
+                "Up",
+                active + 1 < map.layers().len(),
+                Command::MoveLayer { from: active, to: active + 1 },
+            );
+            button(
+                ui,
+                "Down",
+                active > 0,
+                Command::MoveLayer { from: active, to: active.saturating_sub(1) },
+            );
+            button(
+                ui,
+                "Merge Down",
+                active > 0,
+                Command::MergeLayers { from: active, into: active.saturating_sub(1) },
+            );
+        });
+
+        let layer = map.active_layer_mut();
+        ui.horizontal(|ui| {
+            ui.label("Name");
+            ui.text_edit_singleline(&mut layer.name);
+        });
+
+        if let Some(command) = command {
+            tab.apply(command);
+        }
+    });
+}
+
+/// What the prefabs panel keeps between frames.
+#[derive(Default)]
+struct PrefabPanel {
+    library: Option<PrefabLibrary>,
+    prefabs: Vec<LibraryPrefab>,
+    search: String,
+    /// The index of the prefab to stamp.
+    selected: Option<usize>,
+    at: [i32; 3],
+    rotation: Rotation,
+    mirror: Option<Axis>,
+    overwrite: bool,
+    /// The corners of the box to save as a new prefab, and what to call it.
+    save_from: [i32; 3],
+    save_to: [i32; 3],
+    save_name: String,
+    save_tags: String,
+}
+
+impl PrefabPanel {
+    fn refresh(&mut self, errors: &mut Errors) {
+        self.selected = None;
+        if let Some(library) = &self.library {
+            match library.list() {
+                Ok(listing) => {
+                    for (path, err) in listing.failed {
+                        errors.push(format!("Couldn't read {}: {}", path.display(), err));
+                    }
+                    self.prefabs = listing.prefabs;
+                }
+                Err(err) => errors.push(format!("Couldn't read {}: {}", library.dir().display(), err)),
+            }
+        }
+    }
+}
+
+/// Draws a prefab's thumbnail, scaled to fit a square.
+fn thumbnail(ui: &mut egui::Ui, thumbnail: &Thumbnail) {
+    const SIZE: f32 = 48.0;
+
+    let (rect, _) = ui.allocate_exact_size(egui::vec2(SIZE, SIZE), egui::Sense::hover());
+    let painter = ui.painter_at(rect);
+    painter.rect_filled(rect, 0.0, ui.visuals().extreme_bg_color);
+
+    let cell = SIZE / thumbnail.width().max(thumbnail.depth()) as f32;
+    for z in 0..thumbnail.depth() {
+        for x in 0..thumbnail.width() {
+            if let Some(color) = thumbnail.pixel(x, z) {
+                let min = rect.min + egui::vec2(x as f32 * cell, z as f32 * cell);
+                painter.rect_filled(
+                    egui::Rect::from_min_size(min, egui::vec2(cell, cell)),
+                    0.0,
+                    egui::Color32::from_rgba_unmultiplied(color.r, color.g, color.b, color.a),
+                );
+            }
+        }
+    }
+}
+
+fn position_input(ui: &mut egui::Ui, pos: &mut [i32; 3]) {
+    ui.horizontal(|ui| {
+        for coord in pos.iter_mut() {
+            ui.add(egui::DragValue::new(coord));
+        }
+    });
+}
+
+/// Lists the prefabs in the open library, for stamping into the current map, and saves
+/// parts of the map as new ones.
+fn prefabs_panel(ctx: &egui::Context, tab: &mut MapFile, panel: &mut PrefabPanel, errors: &mut Errors) {
+    egui::SidePanel::right("prefabs").show(ctx, |ui| {
+        ui.heading("Prefabs");
+
+        ui.horizontal(|ui| {
+            if ui.button("Open Library").clicked() {
+                if let Some(dir) = rfd::FileDialog::new()
+                    .set_directory(std::env::current_dir().unwrap())
+                    .pick_folder()
+                {
+                    match PrefabLibrary::open(&dir) {
+                        Ok(library) => {
+                            panel.library = Some(library);
+                            panel.refresh(errors);
+                        }
+                        Err(err) => errors.push(format!("Couldn't open {}: {}", dir.display(), err)),
+                    }
+                }
+            }
+            if ui
+                .add_enabled(panel.library.is_some(), egui::Button::new("Refresh"))
+                .clicked()
+            {
+                panel.refresh(errors);
+            }
+        });
+
+        let library = match &panel.library {
+            Some(library) => library.clone(),
+            None => {
+                ui.label("No library open.");
+                return;
+            }
+        };
+        ui.label(library.dir().display().to_string());
+
+        ui.add(egui::TextEdit::singleline(&mut panel.search).hint_text("Search names and tags"));
+        egui::ScrollArea::vertical().max_height(240.0).show(ui, |ui| {
+            for (index, prefab) in panel.prefabs.iter().enumerate() {
+                if !prefab.matches(&panel.search) {
+                    continue;
+                }
+                ui.horizontal(|ui| {
+                    thumbnail(ui, &prefab.thumbnail);
+                    ui.vertical(|ui| {
+                        ui.selectable_value(&mut panel.selected, Some(index), &prefab.name);
+                        let tags: Vec<&str> = prefab.tags.iter().map(String::as_str).collect();
+                        ui.small(tags.join(", "));
+                    });
+                });
+            }
+        });
+
+        ui.separator();
+        let mut deleted = false;
+        if let Some(prefab) = panel.selected.and_then(|index| panel.prefabs.get(index)) {
+            egui::Grid::new("stamp_prefab").num_columns(2).show(ui, |ui| {
+                ui.label("Position");
+                position_input(ui, &mut panel.at);
+                ui.end_row();
+
+                ui.label("Rotation");
+                egui::ComboBox::from_id_source("prefab_rotation")
+                    .selected_text(format!("{}°", panel.rotation.quarter_turns() as u32 * 90))
+                    .show_ui(ui, |ui| {
+                        for rotation in Rotation::ALL {
+                            ui.selectable_value(
+                                &mut panel.rotation,
+                                rotation,
+                                format!("{}°", rotation.quarter_turns() as u32 * 90),
+                            );
+                        }
+                    });
+                ui.end_row();
+
+                ui.label("Mirror");
+                egui::ComboBox::from_id_source("prefab_mirror")
+                    .selected_text(panel.mirror.map_or("None".to_owned(), |axis| format!("{:?}", axis)))
+                    .show_ui(ui, |ui| {
+                        ui.selectable_value(&mut panel.mirror, None, "None");
+                        ui.selectable_value(&mut panel.mirror, Some(Axis::X), "X");
+                        ui.selectable_value(&mut panel.mirror, Some(Axis::Z), "Z");
+                    });
+                ui.end_row();
+
+                ui.label("Overwrite");
+                ui.checkbox(&mut panel.overwrite, "")
+                    .on_hover_text("Empty the cells the prefab leaves empty");
+                ui.end_row();
+            });
+
+            ui.horizontal(|ui| {
+                let stamp = egui::Button::new("Stamp");
+                if ui
+                    .add_enabled(!tab.map().active_layer().locked, stamp)
+                    .on_disabled_hover_text("The layer is locked")
+                    .clicked()
+                {
+                    let pasted = prefab.to_prefab(tab.map_mut().tile_set_mut());
+                    let mut options = PasteOptions::at(panel.at)
+                        .with_rotation(panel.rotation)
+                        .with_mode(if panel.overwrite {
+                            PasteMode::Overwrite
+                        } else {
+                            PasteMode::SkipEmpty
+                        });
+                    options.mirror = panel.mirror;
+                    tab.apply(Command::PastePrefab {
+                        prefab: pasted,
+                        options,
+                    });
+                }
+                if ui.button("Delete").clicked() {
+                    if let Err(err) = library.remove(&prefab.name) {
+                        errors.push(format!("Couldn't delete {}: {}", prefab.name, err));
+                    }
+                    deleted = true;
+                }
+            });
+        } else {
+            ui.label("Select a prefab to stamp it into the map.");
+        }
+        if deleted {
+            panel.refresh(errors);
+        }
+
+        ui.separator();
+        ui.collapsing("Save Prefab", |ui| {
+            egui::Grid::new("save_prefab").num_columns(2).show(ui, |ui| {
+                ui.label("From");
+                position_input(ui, &mut panel.save_from);
+                ui.end_row();
+
+                ui.label("To");
+                position_input(ui, &mut panel.save_to);
+                ui.end_row();
+
+                ui.label("Name");
+                ui.text_edit_singleline(&mut panel.save_name);
+                ui.end_row();
+
+                ui.label("Tags");
+                ui.add(egui::TextEdit::singleline(&mut panel.save_tags).hint_text("Comma separated"));
+                ui.end_row();
+            });
+
+            let name = panel.save_name.trim();
+            if ui.add_enabled(!name.is_empty(), egui::Button::new("Save")).clicked() {
+                let map = tab.map();
+                let [x0, y0, z0] = panel.save_from;
+                let [x1, y1, z1] = panel.save_to;
+                let prefab = LibraryPrefab::new(
+                    name,
+                    map.copy_prefab(Bounds::new((x0, y0, z0), (x1, y1, z1))),
+                    map.tile_set(),
+                );
+                let saved = prefab.and_then(|mut prefab| {
+                    prefab.tags = panel
+                        .save_tags
+                        .split(',')
+                        .map(str::trim)
+                        .filter(|tag| !tag.is_empty())
+                        .map(str::to_owned)
+                        .collect();
+                    library.save(&prefab)
+                });
+
+                match saved {
+                    Ok(()) => {
+                        panel.save_name.clear();
+                        panel.save_tags.clear();
+                        panel.refresh(errors);
+                    }
+                    Err(err) => errors.push(format!("Couldn't save {}: {}", name, err)),
+                }
+            }
+        });
+    });
+}
+
+/// Formats a time as a UTC date and time, like `2024-03-01 14:05`.
+fn format_time(time: SystemTime) -> String {
+    let seconds = match time.duration_since(UNIX_EPOCH) {
+        Ok(since) => since.as_secs() as i64,
+        Err(before) => -(before.duration().as_secs() as i64),
+    };
+    let (days, seconds) = (seconds.div_euclid(86400), seconds.rem_euclid(86400));
+
+    // Converts days since the epoch to a date in the proleptic Gregorian calendar.
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days.rem_euclid(146097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month + 2) / 5 + 1;
+    let month = if month < 10 { month + 3 } else { month - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}",
+        year,
+        month,
+        day,
+        seconds / 3600,
+        seconds % 3600 / 60
+    )
+}
+
+/// Shows the current map's metadata for editing.
+fn properties_window(ctx: &egui::Context, open: &mut bool, tab: &mut MapFile, new_tag: &mut String) {
+    egui::Window::new("Map Properties")
+        .open(open)
+        .resizable(false)
+        .show(ctx, |ui| {
+            let metadata = tab.map_mut().metadata_mut();
+
+            egui::Grid::new("map_properties").num_columns(2).show(ui, |ui| {
+                ui.label("Title");
+                ui.text_edit_singleline(&mut metadata.title);
+                ui.end_row();
+
+                ui.label("Author");
+                ui.text_edit_singleline(&mut metadata.author);
+                ui.end_row();
+
+                ui.label("Description");
+                ui.text_edit_multiline(&mut metadata.description);
+                ui.end_row();
+
+                ui.label("Grid Unit");
+                ui.horizontal(|ui| {
+                    ui.add(egui::DragValue::new(&mut metadata.grid_unit.amount).speed(0.5).clamp_range(0.0..=f32::MAX));
+                    ui.add(egui::TextEdit::singleline(&mut metadata.grid_unit.unit).desired_width(40.0));
+                    ui.label("per cell");
+                });
+                ui.end_row();
+
+                ui.label("Tags");
+                ui.vertical(|ui| {
+                    let mut removed = None;
+                    ui.horizontal_wrapped(|ui| {
+                        for tag in &metadata.tags {
+                            if ui.small_button(format!("{} ✕", tag)).clicked() {
+                                removed = Some(tag.clone());
+                            }
+                        }
+                    });
+                    if let Some(tag) = removed {
+                        metadata.tags.remove(&tag);
+                    }
+
+                    ui.horizontal(|ui| {
+                        ui.text_edit_singleline(new_tag);
+                        if ui.button("Add").clicked() && !new_tag.trim().is_empty() {
+                            metadata.tags.insert(new_tag.trim().to_owned());
+                            new_tag.clear();
+                        }
+                    });
+                });
+                ui.end_row();
+
+                ui.label("Created");
+                ui.label(metadata.created.map_or_else(|| "Not saved yet".to_owned(), format_time));
+                ui.end_row();
+
+                ui.label("Modified");
+                ui.label(metadata.modified.map_or_else(|| "Not saved yet".to_owned(), format_time));
+                ui.end_row();
+            });
+        });
+}
+
+fn draw_ui(
+    mut egui_context: ResMut<EguiContext>,
+    mut tabs: ResMut<Tabs>,
+    mut errors: ResMut<Errors>,
+    // The id of the tab waiting for the user to confirm a format upgrade.
+    mut confirm_upgrade: Local<Option<usize>>,
+    mut show_properties: Local<bool>,
+    mut new_tag: Local<String>,
+    mut prefabs: Local<PrefabPanel>,
+) {
     egui::TopBottomPanel::top("toolbar").show(egui_context.ctx_mut(), |ui| {
         egui::menu::bar(ui, |ui| {
             ui.menu_button("File", |ui| {
                 if ui.button("Open Map").clicked() {
                     if let Some(path) = rfd::FileDialog::new()
-                        .add_filter("Bluprint Maps (*.blu)", &["blu"])
+                        .add_filter("Bluprint Maps (*.blu, *.blub)", &["blu", "blub"])
                         .set_directory(std::env::current_dir().unwrap())
                         .pick_file()
                     {
-                        tabs.new_tab(MapFile::new(path));
+                        match MapFile::open(path.clone()) {
+                            Ok(map_file) => tabs.new_tab(map_file),
+                            Err(err) => {
+                                errors.push(format!("Couldn't open {}: {}", path.display(), err))
+                            }
+                        }
+                    }
+                    ui.close_menu();
+                }
+                if ui.button("Save").clicked() {
+                    if let Some(tab) = tabs.current_tab_mut() {
+                        if tab.is_outdated() {
+                            *confirm_upgrade = Some(tab.id());
+                        } else {
+                            save_tab(tab, &mut errors);
+                        }
+                    }
+                    ui.close_menu();
+                }
+                if ui.button("Save As").clicked() {
+                    if let Some(tab) = tabs.current_tab_mut() {
+                        if let Some(path) = rfd::FileDialog::new()
+                            .add_filter("Bluprint Maps (*.blu)", &["blu"])
+                            .add_filter("Bluprint Binary Maps (*.blub)", &["blub"])
+                            .set_directory(tab.path().parent().unwrap_or(Path::new(".")))
+                            .set_file_name(tab.file_name())
+                            .save_file()
+                        {
+                            if let Err(err) = tab.save_as(path.clone()) {
+                                errors.push(format!("Couldn't save {}: {}", path.display(), err));
+                            }
+                        }
                     }
                     ui.close_menu();
                 }
@@ -26,33 +484,160 @@ fn draw_ui(mut egui_context: ResMut<EguiContext>, mut tabs: ResMut<Tabs>) {
                     ui.close_menu();
                 }
             });
+            ui.menu_button("Edit", |ui| {
+                if let Some(tab) = tabs.current_tab_mut() {
+                    let can_undo = tab.history().can_undo() && !tab.history().undo_is_locked(tab.map());
+                    let can_redo = tab.history().can_redo() && !tab.history().redo_is_locked(tab.map());
+                    if ui
+                        .add_enabled(can_undo, egui::Button::new("Undo"))
+                        .clicked()
+                    {
+                        tab.undo();
+                        ui.close_menu();
+                    }
+                    if ui
+                        .add_enabled(can_redo, egui::Button::new("Redo"))
+                        .clicked()
+                    {
+                        tab.redo();
+                        ui.close_menu();
+                    }
+                }
+            });
+            ui.menu_button("Map", |ui| {
+                if ui.button("Properties").clicked() {
+                    *show_properties = true;
+                    ui.close_menu();
+                }
+                ui.separator();
+                if let Some(tab) = tabs.current_tab_mut() {
+                    // Transforms move every layer, so they're disabled while any is locked.
+                    let transforms = [
+                        ("Rotate 90° Clockwise", Command::RotateY(Rotation::Deg90)),
+                        ("Mirror East to West", Command::Mirror(Axis::X)),
+                        ("Mirror North to South", Command::Mirror(Axis::Z)),
+                    ];
+                    let mut transform = None;
+                    for (label, command) in transforms {
+                        let enabled = !command.is_locked(tab.map());
+                        if ui.add_enabled(enabled, egui::Button::new(label)).clicked() {
+                            transform = Some(command);
+                        }
+                    }
+                    if let Some(transform) = transform {
+                        tab.apply(transform);
+                        ui.close_menu();
+                    }
+                }
+            });
         });
     });
 
+    // Ctrl+Z undoes, and Ctrl+Shift+Z or Ctrl+Y redoes, unless a text field has the keys.
+    let (undo, redo) = {
+        let ctx = egui_context.ctx_mut();
+        let typing = ctx.wants_keyboard_input();
+        let input = ctx.input();
+        let command = input.modifiers.command && !typing;
+        let shift = input.modifiers.shift;
+        (
+            command && !shift && input.key_pressed(egui::Key::Z),
+            command && ((shift && input.key_pressed(egui::Key::Z)) || input.key_pressed(egui::Key::Y)),
+        )
+    };
+    if let Some(tab) = tabs.current_tab_mut() {
+        if undo {
+            tab.undo();
+        }
+        if redo {
+            tab.redo();
+        }
+    }
+
     egui::TopBottomPanel::top("tabbar").show(egui_context.ctx_mut(), |ui| {
         egui::menu::bar(ui, |ui| {
             if let Some(current_tab) = tabs.current_tab_idx() {
                 let mut current_tab = *current_tab;
                 tabs.iter().enumerate().for_each(|(index, tab)| {
-                    ui.selectable_value(&mut current_tab, index, tab.file_name());
+                    ui.selectable_value(&mut current_tab, index, tab.title())
+                        .on_hover_text(tab.path().display().to_string());
                 });
                 tabs.switch_to_tab(current_tab);
             }
         });
     });
-}
 
-fn load_map(
-    mut commands: Commands,
-) {
-    commands.insert_resource(bluprint_core::example::tilemap());
-    commands.insert_resource(crate::world::LoadedChunks::default());
+    if let Some(tab) = tabs.current_tab() {
+        egui::TopBottomPanel::bottom("statusbar").show(egui_context.ctx_mut(), |ui| {
+            let map = tab.map();
+            ui.horizontal(|ui| {
+                ui.label(format!("{} tiles in {} chunks", map.occupied_count(), map.chunk_count()));
+                ui.separator();
+                ui.label(format!("Layer: {}", map.active_layer().name));
+                ui.separator();
+                ui.label(format!("{} per cell", map.metadata().grid_unit));
+            });
+        });
+    }
+
+    if let Some(tab) = tabs.current_tab_mut() {
+        layers_panel(egui_context.ctx_mut(), tab);
+        prefabs_panel(egui_context.ctx_mut(), tab, &mut prefabs, &mut errors);
+        if *show_properties {
+            properties_window(egui_context.ctx_mut(), &mut show_properties, tab, &mut new_tag);
+        }
+    }
+
+    if let Some(tab) = tabs
+        .current_tab_mut()
+        .filter(|tab| Some(tab.id()) == *confirm_upgrade)
+    {
+        egui::Window::new("Upgrade Map")
+            .resizable(false)
+            .collapsible(false)
+            .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+            .show(egui_context.ctx_mut(), |ui| {
+                ui.label(format!(
+                    "{} was saved with map format version {}. Saving it will upgrade it to version {}, \
+                     and older versions of Bluprint won't be able to open it.",
+                    tab.file_name(),
+                    tab.version(),
+                    format::CURRENT_VERSION,
+                ));
+                ui.horizontal(|ui| {
+                    if ui.button("Save").clicked() {
+                        save_tab(tab, &mut errors);
+                        *confirm_upgrade = None;
+                    }
+                    if ui.button("Cancel").clicked() {
+                        *confirm_upgrade = None;
+                    }
+                });
+            });
+    }
+
+    if let Some(message) = errors.current() {
+        let mut dismissed = false;
+        egui::Window::new("Error")
+            .resizable(false)
+            .collapsible(false)
+            .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+            .show(egui_context.ctx_mut(), |ui| {
+                ui.label(message);
+                if ui.button("OK").clicked() {
+                    dismissed = true;
+                }
+            });
+        if dismissed {
+            errors.dismiss();
+        }
+    }
 }
 
 impl Plugin for EditorPlugin {
     fn build(&self, app: &mut App) {
         app.insert_resource(Tabs::default())
-            .add_enter_system(AppState::Editor, load_map)
+            .insert_resource(Errors::default())
             .add_system(draw_ui.run_in_state(AppState::Editor));
     }
 }
@@ -1,15 +1,19 @@
 use iyes_loopless::prelude::*;
 use bevy::pbr::wireframe::{WireframePlugin, Wireframe};
-use bevy::utils::HashSet;
+use bevy::utils::HashMap;
+use bluprint_core::objects::{MapObject, ObjectId};
 use bluprint_core::utils::IntoIterator3D;
-use bluprint_core::tiles::{CHUNK_SIZE_X, CHUNK_SIZE_Y, CHUNK_SIZE_Z};
-use bluprint_core::tiles::TileMap;
+use bluprint_core::tiles::{self, Layer, TileSet};
 use bluprint_core::tiles::TileChunk;
+use bluprint_core::tiles::{ChunkPos, LocalPos, TileType};
 use bevy::render::mesh::Indices;
 use bevy::render::render_resource::PrimitiveTopology;
 use bevy::prelude::*;
 use itertools::MultiUnzip;
 
+use crate::resources::tabs::Tabs;
+use crate::AppState;
+
 #[derive(Clone, Copy)]
 pub enum FaceSide {
     Top,
@@ -183,80 +187,291 @@ impl From<Vec<Face>> for Faces {
     }
 }
 
-fn gen_chunk_mesh(
+/// Builds a mesh for each tile type in a chunk, so that each type can be drawn with its own
+/// material.
+fn gen_chunk_meshes(
     chunk: &TileChunk,
-    chunk_pos: (i32, i32, i32),
-    tile_map: &TileMap,
-) -> Mesh {
-    let mut faces = Vec::new();
-
-    for (x, y, z) in ((0, 0, 0)..(CHUNK_SIZE_X, CHUNK_SIZE_Y, CHUNK_SIZE_Z)).into_3d_iter() {
-        let (fx, fy, fz) = (x as f32, y as f32, z as f32);
-        if let Some(_tile) = chunk.get_tile(x, y, z) {
-            println!("Generating tile");
-            if x > 0 && let None = chunk.get_tile(x - 1, y, z) {
+    chunk_pos: ChunkPos,
+    layer: &Layer,
+    tile_set: &TileSet,
+) -> Vec<(usize, Mesh)> {
+    let mut faces: HashMap<usize, Vec<Face>> = HashMap::default();
+    let size = chunk.size();
+
+    for local in ((0, 0, 0)..(size.x, size.y, size.z)).into_3d_iter() {
+        let local = LocalPos::from(local);
+        let (fx, fy, fz) = (local.x as f32, local.y as f32, local.z as f32);
+        if let Some(tile) = chunk.get_tile(local) {
+            // Neighbours are looked up through the layer, so faces on chunk borders are
+            // culled against the adjacent chunk. A face is only hidden by an opaque
+            // neighbour, or by a neighbour of the same type.
+            let pos = chunk_pos.world_pos(size, local);
+            let is_exposed = |dx, dy, dz| match layer.get_tile_at(pos.offset(dx, dy, dz)) {
+                Some(Some(neighbour)) => {
+                    neighbour.tile_id != tile.tile_id && !tile_set.is_opaque(neighbour.tile_id)
+                }
+                _ => true,
+            };
+            let faces = faces.entry(tile.tile_id).or_default();
+
+            if is_exposed(-1, 0, 0) {
                 faces.push(Face::generate(FaceSide::Left, (fx, fy, fz)));
             }
-            if x < CHUNK_SIZE_X - 1 && let None = chunk.get_tile(x + 1, y, z) {
+            if is_exposed(1, 0, 0) {
                 faces.push(Face::generate(FaceSide::Right, (fx, fy, fz)));
             }
-            if y > 0 && let None = chunk.get_tile(x, y - 1, z) {
+            if is_exposed(0, -1, 0) {
                 faces.push(Face::generate(FaceSide::Bottom, (fx, fy, fz)));
             }
-            if y < CHUNK_SIZE_Y - 1 && let None = chunk.get_tile(x, y + 1, z) {
+            if is_exposed(0, 1, 0) {
                 faces.push(Face::generate(FaceSide::Top, (fx, fy, fz)));
             }
-            if z > 0 && let None = chunk.get_tile(x, y, z - 1) {
+            if is_exposed(0, 0, 1) {
                 faces.push(Face::generate(FaceSide::Front, (fx, fy, fz)));
             }
-            if z < CHUNK_SIZE_Z - 1 && let None = chunk.get_tile(x, y, z + 1) {
+            if is_exposed(0, 0, -1) {
                 faces.push(Face::generate(FaceSide::Back, (fx, fy, fz)));
             }
         }
     }
 
-    let faces: Faces = faces.into();
+    faces
+        .into_iter()
+        .filter(|(_, faces)| !faces.is_empty())
+        .map(|(tile_id, faces)| {
+            let faces: Faces = faces.into();
 
-    let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+            let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
 
-    let inds = Indices::U32(faces.indices);
-    mesh.set_indices(Some(inds));
-    mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, faces.positions);
-    mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, faces.normals);
-    mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, faces.uvs);
+            let inds = Indices::U32(faces.indices);
+            mesh.set_indices(Some(inds));
+            mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, faces.positions);
+            mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, faces.normals);
+            mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, faces.uvs);
 
-    mesh
+            (tile_id, mesh)
+        })
+        .collect()
 }
 
+/// The material for a tile type, taken from the type's color.
+fn tile_material(tile_type: Option<&TileType>) -> StandardMaterial {
+    let color = tile_type.map_or(tiles::Color::default(), |tile_type| tile_type.color);
+    StandardMaterial {
+        base_color: Color::rgba_u8(color.r, color.g, color.b, color.a),
+        alpha_mode: if color.is_opaque() {
+            AlphaMode::Opaque
+        } else {
+            AlphaMode::Blend
+        },
+        ..Default::default()
+    }
+}
+
+/// The chunk meshes currently spawned, and which open map they belong to.
 #[derive(Clone, Default)]
-pub struct LoadedChunks(HashSet<(i32, i32, i32)>);
+pub struct LoadedChunks {
+    map_id: Option<usize>,
+    /// The map's revision when the chunks were spawned.
+    revision: u64,
+    /// Whether each layer was visible when the chunks were spawned.
+    layers: Vec<bool>,
+    /// The meshes for each chunk, keyed by layer index and chunk position.
+    chunks: HashMap<(usize, ChunkPos), Vec<Entity>>,
+    materials: HashMap<usize, Handle<StandardMaterial>>,
+}
+
+impl LoadedChunks {
+    fn clear(&mut self, commands: &mut Commands) {
+        for (_, entities) in self.chunks.drain() {
+            for entity in entities {
+                commands.entity(entity).despawn();
+            }
+        }
+        self.layers.clear();
+        self.materials.clear();
+    }
+}
 
 fn world_render(
     mut commands: Commands,
     mut meshes: ResMut<Assets<Mesh>>,
-    map: Res<TileMap>,
+    mut tabs: ResMut<Tabs>,
     mut materials: ResMut<Assets<StandardMaterial>>,
     mut loaded: ResMut<LoadedChunks>,
 ) {
-    let chunks_to_load = HashSet::from_iter(vec![(0, 0, 0)].into_iter());
+    let current = tabs.current_tab();
 
-    let material_handle = materials.add(StandardMaterial {
-        base_color: Color::PURPLE,
-        ..Default::default()
-    });
-
-    for (cx, cy, cz) in chunks_to_load.difference(&loaded.clone().0) {
-        if let Some(chunk) = map.get_chunk(*cx, *cy, *cz) {
-            let mesh = gen_chunk_mesh(chunk, (*cx, *cy, *cz), &*map);
-            commands.spawn_bundle(PbrBundle {
-                mesh: meshes.add(mesh),
-                material: material_handle.clone(),
-                transform: Transform::from_xyz(*cx as f32 * X_SCALE, *cy as f32 * Y_SCALE, *cz as f32 * Z_SCALE),
+    // Switching or closing tabs throws away everything that was spawned for the old map.
+    if loaded.map_id != current.map(|tab| tab.id()) {
+        loaded.clear(&mut commands);
+        loaded.map_id = current.map(|tab| tab.id());
+    }
+
+    let tab = match tabs.current_tab_mut() {
+        Some(tab) => tab,
+        None => return,
+    };
+
+    // So do changes that can move any tile, like transforming the map or reordering its
+    // layers, since chunks are keyed by layer index. Other edits only throw away the
+    // chunks they changed.
+    let changed = tab.take_changed_chunks();
+    if loaded.revision != tab.revision() {
+        loaded.clear(&mut commands);
+        loaded.revision = tab.revision();
+    } else {
+        for key in changed {
+            for entity in loaded.chunks.remove(&key).into_iter().flatten() {
+                commands.entity(entity).despawn();
+            }
+        }
+    }
+    let map = tab.map();
+
+    // Hiding or showing a layer changes which chunks are drawn.
+    let layers: Vec<bool> = map.layers().iter().map(|layer| layer.visible).collect();
+    if loaded.layers != layers {
+        loaded.clear(&mut commands);
+        loaded.layers = layers;
+    }
+
+    let chunks_to_load: Vec<(usize, ChunkPos)> = map
+        .layers()
+        .iter()
+        .enumerate()
+        .filter(|(_, layer)| layer.visible)
+        .flat_map(|(index, layer)| layer.chunks().map(move |(pos, _)| (index, pos)))
+        .filter(|key| !loaded.chunks.contains_key(key))
+        .collect();
+
+    for (index, cpos) in chunks_to_load {
+        let layer = &map.layers()[index];
+        if let Some(chunk) = layer.get_chunk(cpos) {
+            let origin = cpos.origin(map.chunk_size());
+            let transform = Transform::from_xyz(origin.x as f32 * X_SCALE, origin.y as f32 * Y_SCALE, origin.z as f32 * Z_SCALE);
+
+            let mut entities = Vec::new();
+            for (tile_id, mesh) in gen_chunk_meshes(chunk, cpos, layer, map.tile_set()) {
+                let material = loaded
+                    .materials
+                    .entry(tile_id)
+                    .or_insert_with(|| materials.add(tile_material(map.tile_set().get_tile(tile_id))))
+                    .clone();
+
+                let entity = commands.spawn_bundle(PbrBundle {
+                    mesh: meshes.add(mesh),
+                    material,
+                    transform,
+                    ..Default::default()
+                })
+                .insert(Wireframe)
+                .id();
+                entities.push(entity);
+            }
+            loaded.chunks.insert((index, cpos), entities);
+        }
+    }
+}
+
+/// The marker meshes currently spawned for a map's objects.
+#[derive(Clone, Default)]
+pub struct LoadedObjects {
+    map_id: Option<usize>,
+    /// Each object as it was when its marker was spawned, and the marker.
+    markers: HashMap<ObjectId, (MapObject, Entity)>,
+    mesh: Option<Handle<Mesh>>,
+    materials: HashMap<usize, Handle<StandardMaterial>>,
+}
+
+/// The width and height of an object's marker at a scale of 1.
+const MARKER_WIDTH: f32 = 0.4;
+const MARKER_HEIGHT: f32 = 0.5;
+
+fn marker_transform(object: &MapObject) -> Transform {
+    let pos = object.position;
+    // Markers stand on the object's position, and turn clockwise seen from above.
+    Transform {
+        translation: Vec3::new(
+            pos.x * X_SCALE,
+            pos.y * Y_SCALE + MARKER_HEIGHT * object.scale / 2.0,
+            pos.z * Z_SCALE,
+        ),
+        rotation: Quat::from_rotation_y(-object.rotation.to_radians()),
+        scale: Vec3::splat(object.scale),
+    }
+}
+
+fn objects_render(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    tabs: Res<Tabs>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut loaded: ResMut<LoadedObjects>,
+) {
+    let current = tabs.current_tab();
+
+    if loaded.map_id != current.map(|tab| tab.id()) {
+        for (_, (_, entity)) in loaded.markers.drain() {
+            commands.entity(entity).despawn();
+        }
+        loaded.materials.clear();
+        loaded.map_id = current.map(|tab| tab.id());
+    }
+
+    let map = match current {
+        Some(tab) => tab.map(),
+        None => return,
+    };
+
+    // Markers for objects that were removed or changed are despawned, and the changed
+    // ones are spawned again below.
+    let stale: Vec<ObjectId> = loaded
+        .markers
+        .iter()
+        .filter(|(id, (object, _))| map.objects().get(**id) != Some(object))
+        .map(|(id, _)| *id)
+        .collect();
+    for id in stale {
+        if let Some((_, entity)) = loaded.markers.remove(&id) {
+            commands.entity(entity).despawn();
+        }
+    }
+
+    let mesh = loaded
+        .mesh
+        .get_or_insert_with(|| meshes.add(Mesh::from(shape::Box::new(MARKER_WIDTH, MARKER_HEIGHT, MARKER_WIDTH))))
+        .clone();
+
+    for (id, object) in map.objects().iter() {
+        if loaded.markers.contains_key(&id) {
+            continue;
+        }
+
+        let material = loaded
+            .materials
+            .entry(object.type_id)
+            .or_insert_with(|| {
+                let color = map
+                    .object_types()
+                    .get_type(object.type_id)
+                    .map_or(tiles::Color::default(), |object_type| object_type.color);
+                materials.add(StandardMaterial {
+                    base_color: Color::rgba_u8(color.r, color.g, color.b, color.a),
+                    ..Default::default()
+                })
+            })
+            .clone();
+
+        let entity = commands
+            .spawn_bundle(PbrBundle {
+                mesh: mesh.clone(),
+                material,
+                transform: marker_transform(object),
                 ..Default::default()
             })
-            .insert(Wireframe);
-            loaded.0.insert((*cx, *cy, *cz));
-        }
+            .id();
+        loaded.markers.insert(id, (object.clone(), entity));
     }
 }
 
@@ -275,6 +490,9 @@ impl Plugin for WorldPlugin {
         app
             .add_plugin(WireframePlugin)
             .add_startup_system(setup)
-            .add_system(world_render.run_if_resource_exists::<TileMap>());
+            .init_resource::<LoadedChunks>()
+            .init_resource::<LoadedObjects>()
+            .add_system(world_render.run_in_state(AppState::Editor))
+            .add_system(objects_render.run_in_state(AppState::Editor));
     }
 }